@@ -0,0 +1,136 @@
+extern crate bevy;
+
+use bevy::prelude::*;
+use bevy_flock::behaviours::avoidance::{ObstacleAvoidance, ObstacleAvoidancePlugin};
+use bevy_flock::behaviours::{
+    Alignment, BoidsPlugin, Coherence, DesiredVelocity, Separation, WorldBound,
+};
+use bevy_flock::boid::Boid;
+use bevy_flock::evolution::{
+    Cohesion, Evolution, EvolutionPlugin, Fitness, Survival, WeightedFitness,
+};
+use bevy_flock::flock::{random_direction, BoidsRules, GameArea};
+use bevy_flock::perception::Perception;
+use bevy_flock::physics::Velocity;
+use bevy_flock::predator::Predator;
+use bevy_flock::{BaseFlockBundle, SteeringPlugin};
+
+/// Runs the genetic algorithm without a window, generation results are logged to `evolution.csv`.
+///
+/// Run with `--no-default-features`, the collider debug renderer cannot work headless.
+fn main() {
+    App::new()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(bevy::log::LogPlugin::default())
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(AssetPlugin::default())
+        .add_asset::<Mesh>()
+        .add_asset::<Scene>()
+        .add_plugin(SteeringPlugin)
+        .add_plugin(BoidsPlugin)
+        .add_plugin(ObstacleAvoidancePlugin)
+        .add_plugin(EvolutionPlugin)
+        .insert_resource(GameArea {
+            offset: Vec3::ZERO,
+            area: shape::Box::new(100.0, 100.0, 100.0).into(),
+            mode: default(),
+        })
+        .insert_resource(BoidsRules {
+            desired_speed: 50.0,
+            max_force: 1000.0,
+            max_velocity: 100.0,
+        })
+        .insert_resource(Evolution {
+            generation_time: 30.0,
+            fitness: Box::new(WeightedFitness(vec![
+                (1.0, Box::new(Survival { catch_radius: 2.0 })),
+                (
+                    0.5,
+                    Box::new(Cohesion {
+                        ideal_neighbours: 6,
+                    }),
+                ),
+            ])),
+            log: Some("evolution.csv".into()),
+            ..default()
+        })
+        .add_startup_system(setup)
+        .add_system(predator_chase_system)
+        .run();
+}
+
+fn setup(mut commands: Commands, rules: Res<GameArea>) {
+    let count = 200;
+
+    for _ in 0..count {
+        let boid = BaseFlockBundle {
+            vel: Velocity {
+                vec: random_direction(),
+            },
+            mesh: SceneBundle {
+                transform: rules.random_transform(),
+                ..default()
+            },
+            ..default()
+        };
+
+        commands
+            .spawn(boid)
+            .insert(Perception {
+                range: 10.0,
+                ..default()
+            })
+            .insert(Fitness::default())
+            .insert(Coherence {
+                factor: 4.0,
+                ..default()
+            })
+            .insert(Separation {
+                factor: 8.0,
+                distance: 0.75,
+                ..default()
+            })
+            .insert(Alignment {
+                factor: 2.0,
+                ..default()
+            })
+            .insert(WorldBound { factor: 4.0 })
+            .insert(ObstacleAvoidance {
+                factor: 50.0,
+                ..default()
+            })
+            .insert(DesiredVelocity { factor: 0.1 });
+    }
+
+    for _ in 0..3 {
+        commands.spawn((
+            Predator,
+            Velocity::default(),
+            TransformBundle::from_transform(rules.random_transform()),
+        ));
+    }
+}
+
+/// Predators head straight for the closest boid that has not been caught yet
+fn predator_chase_system(
+    mut predators: Query<(&Transform, &mut Velocity), With<Predator>>,
+    boids: Query<(&Transform, &Fitness), With<Boid>>,
+) {
+    let speed = 60.0;
+
+    for (tf, mut vel) in &mut predators {
+        let target = boids
+            .iter()
+            .filter(|(_, fitness)| fitness.alive)
+            .map(|(boid, _)| boid.translation)
+            .min_by(|a, b| {
+                a.distance_squared(tf.translation)
+                    .total_cmp(&b.distance_squared(tf.translation))
+            });
+
+        if let Some(target) = target {
+            vel.vec = (target - tf.translation).normalize_or_zero() * speed;
+        }
+    }
+}
@@ -2,12 +2,12 @@ extern crate bevy;
 
 use bevy::pbr::CascadeShadowConfigBuilder;
 use bevy::prelude::*;
-use bevy_flock::behaviours::avoidance::ObstacleAvoidance;
+use bevy_flock::behaviours::avoidance::{ObstacleAvoidance, ObstacleAvoidancePlugin};
 use bevy_flock::behaviours::{
     Alignment, BoidsPlugin, Coherence, DesiredVelocity, Separation, WorldBound,
 };
 use bevy_flock::boid::Boid;
-use bevy_flock::flock::{random_direction, random_transform, BoidsRules, GameArea};
+use bevy_flock::flock::{random_direction, BoidsRules, GameArea};
 use bevy_flock::perception::Perception;
 use bevy_flock::{BaseFlockBundle, SteeringPlugin};
 use bevy_flycam::{FlyCam, NoCameraPlayerPlugin};
@@ -21,10 +21,12 @@ fn main() {
         .add_plugins(DefaultPlugins)
         .add_plugin(SteeringPlugin)
         .add_plugin(BoidsPlugin)
+        .add_plugin(ObstacleAvoidancePlugin)
         .add_plugin(NoCameraPlayerPlugin)
         .insert_resource(GameArea {
             offset: Vec3::ZERO,
-            area: shape::Box::new(100.0, 100.0, 100.0),
+            area: shape::Box::new(100.0, 100.0, 100.0).into(),
+            mode: default(),
         })
         .insert_resource(BoidsRules {
             desired_speed: 100.0,
@@ -66,10 +68,11 @@ fn setup(
             acc: Default::default(),
             mesh: SceneBundle {
                 scene: asset_server.load("models/bird.gltf#Scene0"),
-                transform: random_transform(rules.area),
+                transform: rules.random_transform(),
                 ..default()
             },
             integrator: Default::default(),
+            scale: Default::default(),
         };
 
         commands
@@ -78,14 +81,24 @@ fn setup(
                 range: perception,
                 ..default()
             })
-            .insert(Coherence { factor: 4.0 })
+            .insert(Coherence {
+                factor: 4.0,
+                ..default()
+            })
             .insert(Separation {
                 factor: 8.0,
                 distance: 0.75,
+                ..default()
+            })
+            .insert(Alignment {
+                factor: 2.0,
+                ..default()
             })
-            .insert(Alignment { factor: 2.0 })
             .insert(WorldBound { factor: 4.0 })
-            .insert(ObstacleAvoidance { factor: 50.0 })
+            .insert(ObstacleAvoidance {
+                factor: 50.0,
+                ..default()
+            })
             .insert(DesiredVelocity { factor: 0.1 });
     }
 
@@ -0,0 +1,208 @@
+extern crate bevy;
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy_flock::behaviours::{Alignment, BoidsPlugin, Coherence, DesiredVelocity, Separation};
+use bevy_flock::boid::Boid;
+use bevy_flock::flock::{random_direction, BoidsRules, GameArea};
+use bevy_flock::perception::Perception;
+use bevy_flock::physics::Velocity;
+use bevy_flock::surface::{Surface, SurfaceBound, SurfacePlugin};
+use bevy_flock::terrain::Heightmap;
+use bevy_flock::{BaseFlockBundle, SteeringPlugin};
+use bevy_flycam::{FlyCam, NoCameraPlayerPlugin};
+use rand::Rng;
+
+const PLANET_CENTRE: Vec3 = Vec3::new(-60.0, 0.0, 0.0);
+const PLANET_RADIUS: f32 = 30.0;
+
+/// Ants walking on a planet and fish following a seabed, both flocking with the usual behaviours
+fn main() {
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .add_plugin(SteeringPlugin)
+        .add_plugin(BoidsPlugin)
+        .add_plugin(SurfacePlugin)
+        .add_plugin(NoCameraPlayerPlugin)
+        .insert_resource(GameArea {
+            offset: Vec3::ZERO,
+            area: shape::Box::new(400.0, 400.0, 400.0).into(),
+            mode: default(),
+        })
+        .insert_resource(BoidsRules {
+            desired_speed: 10.0,
+            max_force: 100.0,
+            max_velocity: 20.0,
+        })
+        .insert_resource(seabed())
+        .add_startup_system(setup)
+        .run();
+}
+
+/// Rolling dunes to the right of the planet
+fn seabed() -> Heightmap {
+    Heightmap::from_fn(Vec2::new(0.0, -50.0), 2.0, 51, 51, |p| {
+        -30.0 + (p.x * 0.1).sin() * 4.0 + (p.y * 0.07).cos() * 6.0
+    })
+}
+
+fn setup(
+    mut commands: Commands,
+    heightmap: Res<Heightmap>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands
+        .spawn(Camera3dBundle {
+            transform: Transform::from_xyz(0., 60., 150.0).looking_at(Vec3::ZERO, Vec3::Y),
+            ..default()
+        })
+        .insert(FlyCam);
+
+    let mut rng = rand::thread_rng();
+
+    // Ants on the planet
+    let planet = Surface::Sphere {
+        centre: PLANET_CENTRE,
+        radius: PLANET_RADIUS,
+    };
+    for _ in 0..300 {
+        let up = random_unit(&mut rng);
+        let position = PLANET_CENTRE + up * PLANET_RADIUS;
+        let bound = SurfaceBound {
+            altitude: 0.5,
+            normal: up,
+            ..SurfaceBound::new(planet)
+        };
+        spawn_boid(&mut commands, &asset_server, position, bound);
+    }
+
+    // Fish along the seabed
+    for _ in 0..300 {
+        let x = rng.gen_range(5.0..95.0);
+        let z = rng.gen_range(-45.0..45.0);
+        let Some(height) = heightmap.height_at(x, z) else {
+            continue;
+        };
+        let bound = SurfaceBound {
+            altitude: 3.0,
+            ..SurfaceBound::new(Surface::Heightfield)
+        };
+        spawn_boid(&mut commands, &asset_server, Vec3::new(x, height, z), bound);
+    }
+
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(
+            shape::UVSphere {
+                radius: PLANET_RADIUS,
+                ..default()
+            }
+            .into(),
+        ),
+        material: materials.add(Color::rgb(0.4, 0.3, 0.2).into()),
+        transform: Transform::from_translation(PLANET_CENTRE),
+        ..default()
+    });
+
+    commands.spawn(PbrBundle {
+        mesh: meshes.add(heightmap_mesh(&heightmap)),
+        material: materials.add(Color::rgb(0.8, 0.7, 0.5).into()),
+        ..default()
+    });
+
+    commands.spawn(DirectionalLightBundle {
+        transform: Transform::from_xyz(0.0, 100.0, 50.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+fn spawn_boid(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    position: Vec3,
+    bound: SurfaceBound,
+) {
+    let boid = BaseFlockBundle {
+        boid: Boid {
+            color: Color::BLACK,
+        },
+        perception: Default::default(),
+        vel: Velocity {
+            vec: bound.tangent(random_direction()),
+        },
+        acc: Default::default(),
+        mesh: SceneBundle {
+            scene: asset_server.load("models/bird.gltf#Scene0"),
+            transform: Transform::from_translation(position + bound.normal * bound.altitude),
+            ..default()
+        },
+        integrator: Default::default(),
+        scale: Default::default(),
+    };
+
+    commands
+        .spawn(boid)
+        .insert(bound)
+        .insert(Perception {
+            range: 6.0,
+            ..default()
+        })
+        .insert(Coherence {
+            factor: 2.0,
+            ..default()
+        })
+        .insert(Separation {
+            factor: 6.0,
+            distance: 1.0,
+            ..default()
+        })
+        .insert(Alignment {
+            factor: 2.0,
+            ..default()
+        })
+        .insert(DesiredVelocity { factor: 0.5 });
+}
+
+fn random_unit(rng: &mut impl Rng) -> Vec3 {
+    loop {
+        let v = Vec3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        if let Some(unit) = v.try_normalize() {
+            return unit;
+        }
+    }
+}
+
+/// Triangles between the heightmap samples
+fn heightmap_mesh(heightmap: &Heightmap) -> Mesh {
+    let (width, depth) = (heightmap.width, heightmap.depth());
+
+    let mut positions = Vec::with_capacity(width * depth);
+    let mut normals = Vec::with_capacity(width * depth);
+    for z in 0..depth {
+        for x in 0..width {
+            let p = heightmap.origin + Vec2::new(x as f32, z as f32) * heightmap.spacing;
+            positions.push([p.x, heightmap.heights[z * width + x], p.y]);
+            normals.push(heightmap.normal_at(p.x, p.y).unwrap_or(Vec3::Y).to_array());
+        }
+    }
+
+    let mut indices = Vec::new();
+    for z in 0..depth.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let i = (z * width + x) as u32;
+            let (right, below) = (i + 1, i + width as u32);
+            indices.extend([i, below, right, right, below, below + 1]);
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
@@ -0,0 +1,201 @@
+use crate::perception::{perception_system, Perception};
+use crate::physics::Velocity;
+use crate::predator::{nearest_threat, Predator};
+use crate::BoidStage;
+use bevy::prelude::*;
+
+/// How a boid raises and relays the alarm to its neighbours
+#[derive(Component, Clone, Copy)]
+pub struct Alarm {
+    /// Distance at which a predator startles the boid directly
+    pub detection_range: f32,
+    /// Seconds before a startled boid passes the alarm on to its neighbours
+    pub hop_delay: f32,
+    /// Fraction of the startle level kept by each hop
+    pub decay: f32,
+    /// Levels below this are considered calm and are not relayed
+    pub threshold: f32,
+    /// Startle level lost per second
+    pub recovery: f32,
+}
+
+impl Default for Alarm {
+    fn default() -> Self {
+        Self {
+            detection_range: 15.0,
+            hop_delay: 0.1,
+            decay: 0.8,
+            threshold: 0.1,
+            recovery: 0.5,
+        }
+    }
+}
+
+/// Current agitation of a boid, readable by any behaviour
+#[derive(Component, Default, Clone, Copy)]
+pub struct Startle {
+    pub level: f32,
+    /// Number of hops from the boid that saw the threat
+    pub hop: u32,
+    /// The entity that startled this boid, either a predator or a neighbour
+    pub source: Option<Entity>,
+}
+
+impl Startle {
+    pub fn is_startled(&self, alarm: &Alarm) -> bool {
+        self.level >= alarm.threshold
+    }
+}
+
+/// Sent every time a calm boid becomes startled
+pub struct StartleEvent {
+    pub entity: Entity,
+    pub source: Entity,
+    pub hop: u32,
+    pub level: f32,
+}
+
+struct PendingAlarm {
+    entity: Entity,
+    source: Entity,
+    hop: u32,
+    level: f32,
+    at: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct AlarmQueue {
+    pending: Vec<PendingAlarm>,
+}
+
+pub struct AlarmPlugin;
+
+impl Plugin for AlarmPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<StartleEvent>()
+            .init_resource::<AlarmQueue>()
+            .add_systems(
+                (
+                    startle_recovery_system,
+                    alarm_detection_system,
+                    alarm_propagation_system,
+                )
+                    .chain()
+                    .after(perception_system)
+                    .before(BoidStage::ForceCalculation),
+            );
+    }
+}
+
+pub fn startle_recovery_system(mut query: Query<(&mut Startle, &Alarm)>, time: Res<Time>) {
+    for (mut startle, alarm) in &mut query {
+        if startle.level > 0.0 {
+            startle.level = (startle.level - alarm.recovery * time.delta_seconds()).max(0.0);
+        }
+    }
+}
+
+pub fn alarm_detection_system(
+    mut query: Query<(Entity, &Transform, &Perception, &Alarm, &mut Startle)>,
+    predators: Query<(Entity, &Transform, Option<&Velocity>), With<Predator>>,
+    mut queue: ResMut<AlarmQueue>,
+    mut events: EventWriter<StartleEvent>,
+    time: Res<Time>,
+) {
+    for (entity, tf, per, alarm, mut startle) in &mut query {
+        let Some(threat) = nearest_threat(tf.translation, alarm.detection_range, &predators) else {
+            continue;
+        };
+
+        if raise(&mut startle, alarm, 1.0, 0, threat.entity) {
+            events.send(StartleEvent {
+                entity,
+                source: threat.entity,
+                hop: 0,
+                level: 1.0,
+            });
+            relay(
+                &mut queue,
+                entity,
+                per,
+                alarm,
+                &startle,
+                time.elapsed_seconds(),
+            );
+        }
+    }
+}
+
+pub fn alarm_propagation_system(
+    mut query: Query<(&Perception, &Alarm, &mut Startle)>,
+    mut queue: ResMut<AlarmQueue>,
+    mut events: EventWriter<StartleEvent>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    let (due, pending): (Vec<_>, Vec<_>) = queue.pending.drain(..).partition(|p| p.at <= now);
+    queue.pending = pending;
+
+    for alarm in due {
+        // The neighbour may have been despawned since the alarm was scheduled
+        let Ok((per, settings, mut startle)) = query.get_mut(alarm.entity) else {
+            continue;
+        };
+
+        if raise(&mut startle, settings, alarm.level, alarm.hop, alarm.source) {
+            events.send(StartleEvent {
+                entity: alarm.entity,
+                source: alarm.source,
+                hop: alarm.hop,
+                level: alarm.level,
+            });
+            relay(&mut queue, alarm.entity, per, settings, &startle, now);
+        }
+    }
+}
+
+/// Raises the startle level and returns true if the boid was calm before
+fn raise(startle: &mut Startle, alarm: &Alarm, level: f32, hop: u32, source: Entity) -> bool {
+    if level <= startle.level {
+        return false;
+    }
+
+    let was_calm = !startle.is_startled(alarm);
+    *startle = Startle {
+        level,
+        hop,
+        source: Some(source),
+    };
+    was_calm && startle.is_startled(alarm)
+}
+
+/// Schedules the alarm for every perceived neighbour after the hop delay
+fn relay(
+    queue: &mut AlarmQueue,
+    entity: Entity,
+    per: &Perception,
+    alarm: &Alarm,
+    startle: &Startle,
+    now: f32,
+) {
+    let level = startle.level * alarm.decay;
+    if level < alarm.threshold {
+        return;
+    }
+
+    let at = now + alarm.hop_delay;
+    queue
+        .pending
+        .extend(
+            per.list
+                .iter()
+                .filter(|&&e| e != entity)
+                .map(|&neighbour| PendingAlarm {
+                    entity: neighbour,
+                    source: entity,
+                    hop: startle.hop + 1,
+                    level,
+                    at,
+                }),
+        );
+}
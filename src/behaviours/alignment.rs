@@ -1,48 +1,50 @@
-use crate::flock::SteeringPressure;
-use crate::perception::Perception;
-use crate::physics::Velocity;
+use crate::behaviours::falloff::Falloff;
+use crate::behaviours::steering::{Actor, Neighbour, SteeringBehaviour};
+use crate::flock::SteeringPriorities;
 use bevy::prelude::*;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone, Copy)]
 pub struct Alignment {
     pub factor: f32,
+    /// Interaction radius, None uses the `Perception` range
+    pub radius: Option<f32>,
+    pub falloff: Falloff,
 }
 
-pub fn alignment_system(
-    query: Query<(Entity, &Perception, &Alignment, &SteeringPressure)>,
-    boids: Query<(&Transform, &Velocity)>,
-) {
-    for (entity, per, ali, steer) in &query {
-        let neighbours = &per.list;
-        let force = measure_alignment(entity, &boids, neighbours) * ali.factor;
+impl SteeringBehaviour for Alignment {
+    fn steer(&self, actor: &Actor, neighbours: &[Neighbour]) -> Vec3 {
+        let mut total = 0.0;
 
-        let mut vec = steer.lock.write().unwrap();
-        *vec += force;
+        let steer: Vec3 = neighbours
+            .iter()
+            .map(|n| {
+                let weight = n.weight * n.species.alignment;
+                total += weight.abs();
+                // Negative weights steer away from the neighbour's heading
+                (n.velocity - actor.velocity) * weight
+            })
+            .sum();
+
+        if total <= 0.0 {
+            Vec3::ZERO
+        } else {
+            steer / total * actor.scale.alignment
+        }
     }
-}
 
-fn measure_alignment(
-    entity: Entity,
-    query: &Query<(&Transform, &Velocity)>,
-    neighbours: &Vec<Entity>,
-) -> Vec3 {
-    let mut count = 0;
-    let (_, local_mov) = query.get(entity).unwrap();
-
-    let steer: Vec3 = neighbours
-        .into_iter()
-        .filter(|&e| entity != *e)
-        // Get transforms and movement components
-        .map(|e| query.get(*e).unwrap())
-        .map(|(_, &vel)| {
-            count += 1;
-            return vel.vec;
-        })
-        .sum();
-
-    return if count == 0 {
-        Vec3::ZERO
-    } else {
-        (steer / count as f32) - local_mov.vec
-    };
+    fn factor(&self) -> f32 {
+        self.factor
+    }
+
+    fn radius(&self) -> Option<f32> {
+        self.radius
+    }
+
+    fn falloff(&self) -> Falloff {
+        self.falloff
+    }
+
+    fn priority(&self, priorities: &SteeringPriorities) -> u8 {
+        priorities.alignment
+    }
 }
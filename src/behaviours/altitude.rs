@@ -0,0 +1,127 @@
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
+use crate::physics::{ColliderFilters, Velocity};
+use crate::terrain::Heightmap;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// Where the ground under a boid is looked up
+#[derive(Clone, Copy, Debug)]
+pub enum GroundSource {
+    /// The `Heightmap` resource
+    Heightmap,
+    /// Casts a ray down against the obstacle colliders
+    Raycast { max_distance: f32 },
+}
+
+/// Keeps a boid within a height band above the ground
+#[derive(Component, Clone, Copy, Debug)]
+pub struct AltitudePreference {
+    pub factor: f32,
+    /// Lowest preferred height above the ground
+    pub min: f32,
+    /// Highest preferred height above the ground
+    pub max: f32,
+    pub source: GroundSource,
+    /// Seconds ahead the ground is also sampled, so the boid climbs before a slope
+    pub look_ahead: f32,
+    /// Water surface, the ground is never taken to be lower
+    pub sea_level: Option<f32>,
+    /// How fast the followed ground may drop, so a canyon is crossed instead of dived into
+    pub max_descent: f32,
+    /// How strongly speed toward or away from the ground is cancelled outside the band, so the
+    /// boid settles into it instead of bouncing across
+    pub damping: f32,
+    /// Ground height being followed, None before any ground was found
+    pub ground: Option<f32>,
+}
+
+impl Default for AltitudePreference {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            min: 10.0,
+            max: 30.0,
+            source: GroundSource::Heightmap,
+            look_ahead: 1.0,
+            sea_level: None,
+            max_descent: 5.0,
+            damping: 0.5,
+            ground: None,
+        }
+    }
+}
+
+pub fn altitude_system(
+    mut query: Query<(Entity, &Transform, &mut AltitudePreference, SteeringOutput)>,
+    boids: Query<(Option<&Velocity>, Option<&ColliderFilters>)>,
+    heightmap: Option<Res<Heightmap>>,
+    rapier: Res<RapierContext>,
+    filters: Res<ColliderFilters>,
+    priorities: Res<SteeringPriorities>,
+    time: Res<Time>,
+) {
+    for (entity, tf, mut pref, (steer, context)) in &mut query {
+        let pos = tf.translation;
+        let (vel, local) = boids.get(entity).unwrap_or((None, None));
+        let vel = vel.map_or(Vec3::ZERO, |v| v.vec);
+        let filter = filters.local(local).obstacle_filter(entity);
+
+        // Height and upward normal of the ground under a point
+        let ground_at = |point: Vec3| match pref.source {
+            GroundSource::Heightmap => {
+                let heightmap = heightmap.as_ref()?;
+                let height = heightmap.height_at(point.x, point.z)?;
+                Some((height, heightmap.normal_at(point.x, point.z)?))
+            }
+            GroundSource::Raycast { max_distance } => rapier
+                .cast_ray_and_get_normal(point, Vec3::NEG_Y, max_distance, true, filter)
+                .map(|(_, hit)| (hit.point.y, hit.normal)),
+        };
+
+        // The higher of the ground below and ahead, so rising slopes are met early
+        let ahead = pos + vel * pref.look_ahead;
+        let measured = match (ground_at(pos), ground_at(ahead)) {
+            (Some(below), Some(ahead)) => Some(if ahead.0 > below.0 { ahead } else { below }),
+            (below, ahead) => below.or(ahead),
+        };
+        let measured = match (measured, pref.sea_level) {
+            (Some(ground), Some(sea)) if sea > ground.0 => Some((sea, Vec3::Y)),
+            (ground, sea) => ground.or(sea.map(|sea| (sea, Vec3::Y))),
+        };
+
+        // Rising ground is followed at once, falling or missing ground only slowly
+        let floor = pref
+            .ground
+            .map(|g| (g - pref.max_descent * time.delta_seconds(), Vec3::Y));
+        let followed = match (measured, floor) {
+            (Some(ground), Some(floor)) if floor.0 > ground.0 => Some(floor),
+            (ground, floor) => ground.or(floor),
+        };
+        pref.ground = followed.map(|(height, _)| height);
+
+        let Some((ground, normal)) = followed else {
+            continue;
+        };
+
+        let height = pos.y - ground;
+        let correction = if height < pref.min {
+            pref.min - height
+        } else if height > pref.max {
+            pref.max - height
+        } else {
+            continue;
+        };
+
+        // Pushed off or toward the slope along its normal, so the boid follows the ground's
+        // shape instead of only its height
+        let force = normal * (correction * pref.factor - vel.dot(normal) * pref.damping);
+        match context {
+            Some(context) => {
+                context.add_danger(-force, 1.0);
+                context.add_interest(force, force.length());
+            }
+            None => steer.add(priorities.altitude, force),
+        }
+    }
+}
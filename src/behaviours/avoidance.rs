@@ -1,30 +1,258 @@
-use crate::flock::SteeringPressure;
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
 use crate::perception::Perception;
-use crate::physics::{find_nearest_point_on_collider, find_obstacles_in_range};
+use crate::physics::{
+    cast_feeler, find_nearest_point_on_collider, find_obstacles_in_range, ColliderFilters, Velocity,
+};
+use crate::BoidStage;
+use bevy::ecs::system::SystemParam;
 use bevy::math::vec3;
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::Velocity as RapierVelocity;
 use bevy_rapier3d::prelude::*;
 
-#[derive(Component, Default)]
+#[derive(Clone, Copy, Debug, Default)]
+pub enum AvoidanceMode {
+    /// Pushes away from every collider within the perception range
+    #[default]
+    Proximity,
+    /// Casts a feeler along the velocity and steers toward the free direction closest to the
+    /// current heading
+    LookAhead(Feeler),
+}
+
+impl AvoidanceMode {
+    pub fn look_ahead(time: f32, radius: f32) -> Self {
+        AvoidanceMode::LookAhead(Feeler {
+            time,
+            radius,
+            sweep_steps: 6,
+        })
+    }
+}
+
+/// A sphere of `radius` swept along the velocity, `time` seconds ahead
+#[derive(Clone, Copy, Debug)]
+pub struct Feeler {
+    pub time: f32,
+    pub radius: f32,
+    /// Directions tried on each side of the heading, up to a right angle
+    pub sweep_steps: u32,
+}
+
+#[derive(Component, Default, Clone, Copy)]
 pub struct ObstacleAvoidance {
     pub factor: f32,
+    pub mode: AvoidanceMode,
+    /// How many seconds ahead moving obstacles are predicted, 0 treats them as static
+    pub prediction: f32,
+}
+
+/// Steers boids with `ObstacleAvoidance` around rapier colliders, opt-in so that boids that
+/// merely carry the component are not steered by `BoidsPlugin` alone
+pub struct ObstacleAvoidancePlugin;
+
+impl Plugin for ObstacleAvoidancePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(obstacle_motion_system.before(BoidStage::ForceCalculation))
+            .add_system(obstacle_avoidance_system.in_set(BoidStage::ForceCalculation));
+    }
+}
+
+/// Velocity of an obstacle measured from how its `Transform` moves, for obstacles that are
+/// moved without a rapier `Velocity`
+#[derive(Component, Default, Clone, Copy)]
+pub struct ObstacleMotion {
+    pub velocity: Vec3,
+    previous: Option<Vec3>,
+}
+
+pub fn obstacle_motion_system(
+    mut query: Query<(&Transform, &mut ObstacleMotion)>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    for (tf, mut motion) in &mut query {
+        if let Some(previous) = motion.previous {
+            motion.velocity = (tf.translation - previous) / delta;
+        }
+        motion.previous = Some(tf.translation);
+    }
+}
+
+/// Velocities of moving obstacles
+#[derive(SystemParam)]
+pub struct ObstacleVelocities<'w, 's> {
+    motions: Query<'w, 's, &'static ObstacleMotion>,
+    bodies: Query<'w, 's, &'static RapierVelocity>,
+}
+
+impl ObstacleVelocities<'_, '_> {
+    /// The obstacle's velocity, None when it does not move
+    pub fn get(&self, entity: Entity) -> Option<Vec3> {
+        let velocity = match self.motions.get(entity) {
+            Ok(motion) => motion.velocity,
+            Err(_) => self.bodies.get(entity).ok()?.linvel,
+        };
+        (velocity != Vec3::ZERO).then_some(velocity)
+    }
+}
+
+/// Offset from an obstacle to the boid at their closest approach within `horizon` seconds
+///
+/// # Arguments
+///
+/// * `offset`: From the obstacle to the boid now
+/// * `relative_velocity`: Velocity of the boid relative to the obstacle
+/// * `horizon`: How far ahead to look
+///
+/// returns: Vec3
+pub fn closest_approach(offset: Vec3, relative_velocity: Vec3, horizon: f32) -> Vec3 {
+    offset + relative_velocity * approach_time(offset, relative_velocity, horizon)
+}
+
+/// Seconds until the closest approach, between 0 and `horizon`
+pub fn approach_time(offset: Vec3, relative_velocity: Vec3, horizon: f32) -> f32 {
+    let speed_sq = relative_velocity.length_squared();
+    if speed_sq <= f32::EPSILON {
+        return 0.0;
+    }
+
+    (-offset.dot(relative_velocity) / speed_sq).clamp(0.0, horizon)
+}
+
+/// Push away from a moving obstacle, up to `range` long, stronger the closer it will pass by
+/// and the sooner it gets there
+fn predicted_push(offset: Vec3, relative_velocity: Vec3, horizon: f32, range: f32) -> Vec3 {
+    let time = approach_time(offset, relative_velocity, horizon);
+    let miss = offset + relative_velocity * time;
+    let closeness = 1.0 - (miss.length() / range).min(1.0);
+    let soon = 1.0 - time / horizon;
+
+    miss.try_normalize()
+        .unwrap_or_else(|| offset.normalize_or_zero())
+        * range
+        * closeness
+        * soon
 }
 
 pub fn obstacle_avoidance_system(
     query: Query<(
+        Entity,
         &Transform,
         &Perception,
         &ObstacleAvoidance,
-        &SteeringPressure,
+        SteeringOutput,
     )>,
+    velocities: Query<&Velocity>,
+    obstacles: ObstacleVelocities,
+    local_filters: Query<&ColliderFilters>,
+    filters: Res<ColliderFilters>,
     rapier: Res<RapierContext>,
+    priorities: Res<SteeringPriorities>,
 ) {
-    for (tf, perception, avoid, pressure) in &query {
-        let entities = find_obstacles_in_range(&rapier, perception.range, tf.translation);
-        let force = obstacle_avoid_steering(&rapier, tf.translation, &entities) * avoid.factor;
+    for (entity, tf, perception, avoid, (pressure, context)) in &query {
+        let vel = velocities.get(entity).map_or(Vec3::ZERO, |v| v.vec);
+        let filter = filters
+            .local(local_filters.get(entity).ok())
+            .obstacle_filter(entity);
+
+        if let AvoidanceMode::LookAhead(feeler) = avoid.mode {
+            // The feeler only sees where obstacles are now, moving ones are predicted on top
+            if avoid.prediction > 0.0 {
+                let moving: Vec<Entity> =
+                    find_obstacles_in_range(&rapier, perception.range, tf.translation, filter)
+                        .into_iter()
+                        .filter(|e| obstacles.get(*e).is_some())
+                        .collect();
+                let push = obstacle_avoid_steering(
+                    &rapier,
+                    &obstacles,
+                    tf.translation,
+                    vel,
+                    avoid.prediction,
+                    perception.range,
+                    &moving,
+                );
+                match context {
+                    // The push grows up to the perception range as a collision gets closer
+                    Some(context) if push != Vec3::ZERO => {
+                        let urgency = (push.length() / perception.range).min(1.0);
+                        context.add_danger(-push, urgency * avoid.factor);
+                    }
+                    Some(_) => {}
+                    None => pressure.add(priorities.avoidance, push * avoid.factor),
+                }
+            }
+
+            // Local Z is the boid's up, it faces along local X
+            let up = tf.rotation * Vec3::Z;
+            let Some((urgency, heading)) =
+                look_ahead_steering(&rapier, tf.translation, vel, up, feeler, filter)
+            else {
+                continue;
+            };
+
+            match context {
+                Some(context) => {
+                    context.add_danger(vel, urgency);
+                    context.add_interest(heading, urgency * avoid.factor);
+                }
+                None => {
+                    let force = (heading * vel.length() - vel) * urgency * avoid.factor;
+                    pressure.add(priorities.avoidance, force);
+                }
+            }
+            continue;
+        }
+
+        let entities = find_obstacles_in_range(&rapier, perception.range, tf.translation, filter);
 
-        let mut vec = pressure.lock.write().unwrap();
-        *vec += force;
+        if let Some(context) = context {
+            // Mark the way to each obstacle as dangerous instead of averaging the way out
+            for e in &entities {
+                // The collider may have been removed since it was found
+                let Some(point) = find_nearest_point_on_collider(&rapier, tf.translation, *e)
+                else {
+                    continue;
+                };
+                let (offset, urgency) = match obstacles.get(*e).filter(|_| avoid.prediction > 0.0) {
+                    Some(obstacle_vel) => {
+                        let push = predicted_push(
+                            tf.translation - point,
+                            vel - obstacle_vel,
+                            avoid.prediction,
+                            perception.range,
+                        );
+                        (-push, push.length() / perception.range)
+                    }
+                    None => {
+                        let offset = point - tf.translation;
+                        (offset, 1.0 - (offset.length() / perception.range).min(1.0))
+                    }
+                };
+                if offset != Vec3::ZERO {
+                    context.add_danger(offset, urgency * avoid.factor);
+                }
+            }
+            continue;
+        }
+
+        let force = obstacle_avoid_steering(
+            &rapier,
+            &obstacles,
+            tf.translation,
+            vel,
+            avoid.prediction,
+            perception.range,
+            &entities,
+        ) * avoid.factor;
+
+        pressure.add(priorities.avoidance, force);
 
         // Only for debug, but broken for now
         //for e in entities {
@@ -36,20 +264,30 @@ pub fn obstacle_avoidance_system(
 
 fn obstacle_avoid_steering(
     context: &Res<RapierContext>,
+    obstacles: &ObstacleVelocities,
     actor_pos: Vec3,
+    actor_vel: Vec3,
+    prediction: f32,
+    range: f32,
     entities: &Vec<Entity>,
 ) -> Vec3 {
     let mut count = 0;
 
     let mut steer: Vec3 = entities
         .into_iter()
-        .map(|e| {
+        .filter_map(|e| Some((e, find_nearest_point_on_collider(context, actor_pos, *e)?)))
+        .map(|(e, point)| {
             count += 1;
 
-            let point = find_nearest_point_on_collider(context, actor_pos, *e);
             let separation = -1.0 * (point - actor_pos);
 
-            separation
+            // Push away from where a moving obstacle will be closest, as hard as the miss is near
+            match obstacles.get(*e).filter(|_| prediction > 0.0) {
+                Some(obstacle_vel) => {
+                    predicted_push(separation, actor_vel - obstacle_vel, prediction, range)
+                }
+                None => separation,
+            }
         })
         .sum();
 
@@ -60,3 +298,105 @@ fn obstacle_avoid_steering(
     let steering = vec3(steer.x, steer.y, steer.z);
     return steering;
 }
+
+/// Feeler in front of the boid, returns how close the hit is (0 far, 1 touching) and the free
+/// direction to steer toward, or None when the way ahead is clear
+fn look_ahead_steering(
+    context: &Res<RapierContext>,
+    pos: Vec3,
+    vel: Vec3,
+    up: Vec3,
+    feeler: Feeler,
+    filter: QueryFilter,
+) -> Option<(f32, Vec3)> {
+    let speed = vel.length();
+    if speed <= f32::EPSILON {
+        return None;
+    }
+
+    let heading = vel / speed;
+    let distance = speed * feeler.time;
+    let (toi, normal) = cast_feeler(context, pos, heading, feeler.radius, distance, filter)?;
+    let urgency = 1.0 - (toi / distance).clamp(0.0, 1.0);
+
+    // Turn away from the surface, around the axis the heading would take to reach the normal
+    let cross = heading.cross(normal);
+    let axis = if cross.length_squared() > 1e-6 {
+        cross.normalize()
+    } else {
+        // Head-on, turn around the boid's own up so that it keeps its attitude
+        (up - heading * heading.dot(up))
+            .try_normalize()
+            .unwrap_or_else(|| heading.any_orthonormal_vector())
+    };
+
+    // Try the directions closest to the heading first
+    let steps = feeler.sweep_steps.max(1);
+    for step in 1..=steps {
+        let angle = std::f32::consts::FRAC_PI_2 * step as f32 / steps as f32;
+        for side in [-1.0, 1.0] {
+            let candidate = Quat::from_axis_angle(axis, angle * side) * heading;
+            if cast_feeler(context, pos, candidate, feeler.radius, distance, filter).is_none() {
+                return Some((urgency, candidate));
+            }
+        }
+    }
+
+    // Boxed in, slide along the surface
+    let tangent = (heading - normal * heading.dot(normal)).normalize_or_zero();
+    Some((
+        urgency,
+        if tangent == Vec3::ZERO {
+            normal
+        } else {
+            tangent
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closest_approach_of_a_passing_obstacle() {
+        // Passing 2 units to the side, 5 seconds away
+        let offset = vec3(-10.0, 2.0, 0.0);
+        let velocity = vec3(2.0, 0.0, 0.0);
+        assert_eq!(approach_time(offset, velocity, 10.0), 5.0);
+        assert_eq!(
+            closest_approach(offset, velocity, 10.0),
+            vec3(0.0, 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn closest_approach_is_limited_to_the_horizon() {
+        let offset = vec3(-10.0, 2.0, 0.0);
+        let velocity = vec3(2.0, 0.0, 0.0);
+        assert_eq!(approach_time(offset, velocity, 2.0), 2.0);
+        assert_eq!(
+            closest_approach(offset, velocity, 2.0),
+            vec3(-6.0, 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn receding_or_still_obstacles_are_closest_now() {
+        let offset = vec3(3.0, 0.0, 0.0);
+        assert_eq!(closest_approach(offset, Vec3::X, 10.0), offset);
+        assert_eq!(closest_approach(offset, Vec3::ZERO, 10.0), offset);
+    }
+
+    #[test]
+    fn predicted_push_grows_with_a_near_and_soon_miss() {
+        let velocity = vec3(2.0, 0.0, 0.0);
+        let near = predicted_push(vec3(-4.0, 1.0, 0.0), velocity, 10.0, 10.0);
+        let wide = predicted_push(vec3(-4.0, 5.0, 0.0), velocity, 10.0, 10.0);
+        let late = predicted_push(vec3(-16.0, 1.0, 0.0), velocity, 10.0, 10.0);
+
+        assert!(near.length() > wide.length());
+        assert!(near.length() > late.length());
+        assert!(near.normalize().abs_diff_eq(Vec3::Y, 1e-6));
+    }
+}
@@ -1,54 +1,201 @@
-use crate::flock::{GameArea, SteeringPressure};
+use crate::context::SteeringOutput;
+use crate::flock::{BoundaryMode, GameArea, SteeringPriorities};
+use crate::physics::Velocity;
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::RapierContext;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone, Copy)]
 pub struct WorldBound {
     pub factor: f32,
 }
 
+/// Spawns the boid replacing one despawned by `BoundaryMode::Respawn`, at the given transform.
+/// Without it those boids are only despawned.
+#[derive(Resource)]
+pub struct BoundarySpawner(pub fn(&mut Commands, Transform) -> Entity);
+
+/// Sent when a boid crosses the edge of the area. Boids under `Reflect`, `Clamp` and `Wrap` are
+/// put back straight away, so their `Exited` is followed by `Entered` in the same frame. A
+/// boid under `Respawn` is despawned instead, and its replacement is the one that enters
+pub enum BoundaryEvent {
+    Exited {
+        boid: Entity,
+        mode: BoundaryMode,
+        position: Vec3,
+        normal: Vec3,
+    },
+    Entered {
+        boid: Entity,
+        position: Vec3,
+    },
+    /// A boid under `Respawn` was despawned, `replacement` is None without a `BoundarySpawner`
+    Respawned {
+        boid: Entity,
+        replacement: Option<Entity>,
+    },
+}
+
+/// Whether a boid was inside the area when the boundary modes were last applied, inserted by
+/// `boundary_response_system`
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BoundaryCrossing {
+    pub inside: bool,
+}
+
+/// Steers boids under the `Spring` and `Soft` modes back into the area
 pub fn boundaries_system(
-    mut query: Query<(&Transform, &WorldBound, &SteeringPressure)>,
+    mut query: Query<(
+        &Transform,
+        &WorldBound,
+        Option<&BoundaryMode>,
+        SteeringOutput,
+    )>,
+    rules: Res<GameArea>,
+    rapier: Option<Res<RapierContext>>,
+    priorities: Res<SteeringPriorities>,
+) {
+    let rapier = rapier.as_deref();
+
+    for (tf, bound, mode, (steer, context)) in &mut query {
+        let depth = match mode.copied().unwrap_or(rules.mode) {
+            BoundaryMode::Spring => 0.0,
+            BoundaryMode::Soft { margin } => margin.max(0.0),
+            _ => continue,
+        };
+
+        let Some((distance, normal)) = rules.boundary(tf.translation, rapier) else {
+            continue;
+        };
+        // How far past the edge, or past the start of the margin
+        let distance = distance + depth;
+        if distance <= 0.0 {
+            continue;
+        }
+
+        let force = -normal * distance * bound.factor;
+        match context {
+            // Heading further out is dangerous, heading back in is of interest
+            Some(context) => {
+                context.add_danger(-force, 1.0);
+                context.add_interest(force, force.length());
+            }
+            None => steer.add(priorities.bounds, force),
+        }
+    }
+}
+
+/// A boid the hard boundary modes apply to
+type BoundedBoid = (
+    Entity,
+    &'static mut Transform,
+    &'static mut Velocity,
+    Option<&'static BoundaryMode>,
+    Option<&'static mut BoundaryCrossing>,
+);
+
+/// Applies the hard boundary modes once the boids moved, and reports crossings
+pub fn boundary_response_system(
+    mut commands: Commands,
+    mut query: Query<BoundedBoid, With<WorldBound>>,
     rules: Res<GameArea>,
+    rapier: Option<Res<RapierContext>>,
+    spawner: Option<Res<BoundarySpawner>>,
+    mut events: EventWriter<BoundaryEvent>,
 ) {
-    for (tf, bound, steer) in &mut query {
-        let mut force = Vec3::ZERO;
-        if tf.translation.x >= rules.area.max_x {
-            // Right X bound
-            let delta = rules.area.max_x - tf.translation.x;
-            force.x = delta * bound.factor;
-        } else if tf.translation.x <= rules.area.min_x {
-            // Left X bound
-            let delta = rules.area.min_x - tf.translation.x;
-            force.x = delta * bound.factor;
+    let rapier = rapier.as_deref();
+
+    for (entity, mut tf, mut vel, mode, crossing) in &mut query {
+        let mode = mode.copied().unwrap_or(rules.mode);
+        let Some((distance, normal)) = rules.boundary(tf.translation, rapier) else {
+            continue;
+        };
+        let inside = distance <= 0.0;
+        // A boid seen for the first time starts where it is, without an event
+        let was_inside = crossing.as_ref().map_or(inside, |c| c.inside);
+
+        if inside {
+            if !was_inside {
+                events.send(BoundaryEvent::Entered {
+                    boid: entity,
+                    position: tf.translation,
+                });
+            }
+            record_crossing(&mut commands, entity, crossing, true);
+            continue;
         }
 
-        if tf.translation.y <= rules.area.min_y {
-            //.bottom {
-            // Lower Y bound
-            let delta = rules.area.min_y - tf.translation.y;
-            force.y = delta * bound.factor;
-        } else if tf.translation.y >= rules.area.max_y {
-            //.top {
-            // Top Y bound
-            let delta = rules.area.max_y - tf.translation.y;
-            force.y = delta * bound.factor;
+        if was_inside {
+            events.send(BoundaryEvent::Exited {
+                boid: entity,
+                mode,
+                position: tf.translation,
+                normal,
+            });
         }
 
-        if tf.translation.z <= rules.area.min_z {
-            //.bottom {
-            // Lower Z bound
-            let delta = rules.area.min_z - tf.translation.z;
-            force.z = delta * bound.factor;
-        } else if tf.translation.z >= rules.area.max_z {
-            //.top {
-            // Top Z bound
-            let delta = rules.area.max_z - tf.translation.z;
-            force.z = delta * bound.factor;
+        let outward = vel.vec.dot(normal);
+        match mode {
+            BoundaryMode::Spring | BoundaryMode::Soft { .. } => {
+                record_crossing(&mut commands, entity, crossing, false);
+                continue;
+            }
+            BoundaryMode::Reflect => {
+                tf.translation -= normal * distance * 2.0;
+                if outward > 0.0 {
+                    vel.vec -= normal * outward * 2.0;
+                }
+            }
+            BoundaryMode::Clamp => {
+                tf.translation -= normal * distance;
+                if outward > 0.0 {
+                    vel.vec -= normal * outward;
+                }
+            }
+            BoundaryMode::Wrap => tf.translation = rules.wrap(tf.translation, rapier),
+            BoundaryMode::Respawn => {
+                commands.entity(entity).despawn_recursive();
+
+                // Fall back to the closest point inside when sampling keeps missing the area
+                let position = rules
+                    .random_point(rapier)
+                    .unwrap_or(tf.translation - normal * distance);
+                let replacement = spawner.as_ref().map(|spawner| {
+                    (spawner.0)(&mut commands, Transform::from_translation(position))
+                });
+
+                events.send(BoundaryEvent::Respawned {
+                    boid: entity,
+                    replacement,
+                });
+                if let Some(replacement) = replacement {
+                    events.send(BoundaryEvent::Entered {
+                        boid: replacement,
+                        position,
+                    });
+                }
+                continue;
+            }
         }
 
-        if force != Vec3::ZERO {
-            let mut vec = steer.lock.write().unwrap();
-            *vec += force;
+        // Put back inside straight away
+        events.send(BoundaryEvent::Entered {
+            boid: entity,
+            position: tf.translation,
+        });
+        record_crossing(&mut commands, entity, crossing, true);
+    }
+}
+
+fn record_crossing(
+    commands: &mut Commands,
+    entity: Entity,
+    crossing: Option<Mut<BoundaryCrossing>>,
+    inside: bool,
+) {
+    match crossing {
+        Some(mut crossing) => crossing.inside = inside,
+        None => {
+            commands.entity(entity).insert(BoundaryCrossing { inside });
         }
     }
 }
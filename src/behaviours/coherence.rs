@@ -1,44 +1,80 @@
-use crate::flock::SteeringPressure;
+use crate::behaviours::falloff::Falloff;
+use crate::behaviours::scale::FactorScale;
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
 use crate::perception::Perception;
+use crate::species::SpeciesLookup;
+use crate::surface::SurfaceLookup;
 use bevy::prelude::*;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone, Copy)]
 pub struct Coherence {
     pub factor: f32,
+    /// Interaction radius, None uses the `Perception` range
+    pub radius: Option<f32>,
+    pub falloff: Falloff,
 }
 
 pub fn coherence_system(
-    query: Query<(Entity, &Perception, &Coherence, &SteeringPressure)>,
+    query: Query<(
+        Entity,
+        &Perception,
+        &Coherence,
+        SteeringOutput,
+        Option<&FactorScale>,
+    )>,
     boids: Query<&Transform>,
+    species: SpeciesLookup,
+    surfaces: SurfaceLookup,
+    priorities: Res<SteeringPriorities>,
 ) {
-    for (entity, per, coh, steer) in query.iter() {
+    for (entity, per, coh, (steer, context), scale) in query.iter() {
         let neighbours = &per.list;
-        let force = measure_coherence(entity, &boids, neighbours) * coh.factor;
+        let factor = coh.factor * scale.map_or(1.0, |s| s.coherence);
+        let force = measure_coherence(
+            entity, &boids, &species, &surfaces, neighbours, coh, per.range,
+        ) * factor;
 
-        let mut vec = steer.lock.write().unwrap();
-        *vec += force;
+        match context {
+            Some(context) => context.add_interest(force, force.length()),
+            None => steer.add(priorities.coherence, force),
+        }
     }
 }
 
-fn measure_coherence(entity: Entity, query: &Query<&Transform>, neighbours: &Vec<Entity>) -> Vec3 {
+fn measure_coherence(
+    entity: Entity,
+    query: &Query<&Transform>,
+    species: &SpeciesLookup,
+    surfaces: &SurfaceLookup,
+    neighbours: &Vec<Entity>,
+    coh: &Coherence,
+    range: f32,
+) -> Vec3 {
     let local_tf = query.get(entity).unwrap();
-    let mut count = 0;
+    let radius = coh.radius.unwrap_or(range);
+    let mut total = 0.0;
 
+    // Offset to the centre of the neighbours, weighted by distance and species
     let steer: Vec3 = neighbours
         .into_iter()
+        .filter(|&&e| e != entity)
         .map(|&e| {
-            if e == entity {
-                return Vec3::ZERO;
-            }
-            let tf = query.get(e).unwrap();
-            count += 1;
-            tf.translation
+            let offset = surfaces.offset(
+                entity,
+                local_tf.translation,
+                query.get(e).unwrap().translation,
+            );
+            let weight = coh.falloff.weight(offset.length(), radius);
+            let weight = weight * species.weights(entity, e).coherence;
+            total += weight.abs();
+            offset * weight
         })
         .sum();
 
-    return if count == 0 {
+    return if total <= 0.0 {
         Vec3::ZERO
     } else {
-        (steer / count as f32) - local_tf.translation
+        steer / total
     };
 }
@@ -0,0 +1,282 @@
+use crate::behaviours::scale::FactorScale;
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
+use crate::physics::Velocity;
+use crate::predator::{nearest_threat, Predator, Threat};
+use bevy::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Manoeuvre {
+    /// Burst away from the predator in every direction
+    FlashExpansion,
+    /// Split sideways around the predator and regroup behind it
+    Fountain,
+    /// Keep a hollow around the predator while the flock holds together
+    Vacuole,
+}
+
+/// How a manoeuvre fades back to the normal weights once it is over
+#[derive(Clone, Copy, Debug, Default)]
+pub enum RecoveryCurve {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    SmoothStep,
+    Step,
+}
+
+impl RecoveryCurve {
+    /// Fraction recovered at `t`, where `t` goes from 0 (start of recovery) to 1 (recovered)
+    pub fn sample(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            RecoveryCurve::Linear => t,
+            RecoveryCurve::EaseIn => t * t,
+            RecoveryCurve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            RecoveryCurve::SmoothStep => t * t * (3.0 - 2.0 * t),
+            RecoveryCurve::Step => {
+                if t >= 1.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Weights used while a manoeuvre is active.
+///
+/// The behaviour multipliers are applied to the boid's own `Coherence`, `Separation`
+/// and `Alignment` factors through its `FactorScale`.
+#[derive(Clone, Copy, Debug)]
+pub struct ManoeuvreProfile {
+    pub duration: f32,
+    pub recovery: f32,
+    pub curve: RecoveryCurve,
+    pub coherence: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub force: f32,
+}
+
+impl ManoeuvreProfile {
+    /// Strength of the manoeuvre after `elapsed` seconds, or None once fully recovered
+    pub fn strength(&self, elapsed: f32) -> Option<f32> {
+        if elapsed < self.duration {
+            Some(1.0)
+        } else if elapsed < self.duration + self.recovery {
+            let t = (elapsed - self.duration) / self.recovery;
+            Some(1.0 - self.curve.sample(t))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ActiveManoeuvre {
+    pub kind: Manoeuvre,
+    pub predator: Entity,
+    pub elapsed: f32,
+    /// Last known position and heading of the predator
+    pub position: Vec3,
+    pub heading: Vec3,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Evasion {
+    /// Distance at which a predator triggers a manoeuvre
+    pub range: f32,
+    /// Below this distance the boid always flashes away
+    pub flash_range: f32,
+    /// A flash expansion holds until the predator is this fraction beyond `flash_range`, so a
+    /// predator hovering at the edge does not make the flock flicker between manoeuvres
+    pub flash_hysteresis: f32,
+    /// Cosine of the cone in front of the predator that triggers a fountain
+    pub fountain_cone: f32,
+    /// Radius of the hollow kept around the predator
+    pub vacuole_radius: f32,
+    pub flash_expansion: ManoeuvreProfile,
+    pub fountain: ManoeuvreProfile,
+    pub vacuole: ManoeuvreProfile,
+    pub active: Option<ActiveManoeuvre>,
+}
+
+impl Default for Evasion {
+    fn default() -> Self {
+        Self {
+            range: 20.0,
+            flash_range: 5.0,
+            flash_hysteresis: 0.25,
+            fountain_cone: 0.8,
+            vacuole_radius: 10.0,
+            flash_expansion: ManoeuvreProfile {
+                duration: 0.3,
+                recovery: 1.5,
+                curve: RecoveryCurve::EaseOut,
+                coherence: 0.0,
+                separation: 4.0,
+                alignment: 0.0,
+                force: 200.0,
+            },
+            fountain: ManoeuvreProfile {
+                duration: 1.0,
+                recovery: 2.0,
+                curve: RecoveryCurve::SmoothStep,
+                coherence: 0.5,
+                separation: 1.5,
+                alignment: 0.5,
+                force: 100.0,
+            },
+            vacuole: ManoeuvreProfile {
+                duration: 0.5,
+                recovery: 1.0,
+                curve: RecoveryCurve::Linear,
+                coherence: 1.5,
+                separation: 1.5,
+                alignment: 0.75,
+                force: 80.0,
+            },
+            active: None,
+        }
+    }
+}
+
+impl Evasion {
+    pub fn profile(&self, kind: Manoeuvre) -> &ManoeuvreProfile {
+        match kind {
+            Manoeuvre::FlashExpansion => &self.flash_expansion,
+            Manoeuvre::Fountain => &self.fountain,
+            Manoeuvre::Vacuole => &self.vacuole,
+        }
+    }
+
+    /// Picks the manoeuvre matching how the predator approaches, given the one already running
+    pub fn select(&self, position: Vec3, threat: &Threat, current: Option<Manoeuvre>) -> Manoeuvre {
+        let flash_range = if current == Some(Manoeuvre::FlashExpansion) {
+            self.flash_range * (1.0 + self.flash_hysteresis)
+        } else {
+            self.flash_range
+        };
+        if threat.distance <= flash_range {
+            return Manoeuvre::FlashExpansion;
+        }
+
+        let to_boid = (position - threat.position).normalize_or_zero();
+        let heading = threat.velocity.normalize_or_zero();
+        if heading.dot(to_boid) >= self.fountain_cone {
+            Manoeuvre::Fountain
+        } else {
+            Manoeuvre::Vacuole
+        }
+    }
+}
+
+pub fn evasion_system(
+    mut query: Query<(&Transform, &mut Evasion, &mut FactorScale, SteeringOutput)>,
+    predators: Query<(Entity, &Transform, Option<&Velocity>), With<Predator>>,
+    time: Res<Time>,
+    priorities: Res<SteeringPriorities>,
+) {
+    for (tf, mut evasion, mut scale, (steer, context)) in &mut query {
+        let pos = tf.translation;
+
+        if let Some(threat) = nearest_threat(pos, evasion.range, &predators) {
+            let current = evasion.active.map(|a| a.kind);
+            let kind = evasion.select(pos, &threat, current);
+            let duration = evasion.profile(kind).duration;
+
+            evasion.active = match evasion.active {
+                // Hold the manoeuvre at full strength while the threat persists
+                Some(active) if active.kind == kind && active.predator == threat.entity => {
+                    Some(ActiveManoeuvre {
+                        elapsed: active.elapsed.min(duration),
+                        position: threat.position,
+                        heading: threat.velocity.normalize_or_zero(),
+                        ..active
+                    })
+                }
+                // Another manoeuvre only takes over once the running one has played out
+                Some(active)
+                    if active.predator == threat.entity
+                        && active.elapsed < evasion.profile(active.kind).duration =>
+                {
+                    Some(ActiveManoeuvre {
+                        position: threat.position,
+                        heading: threat.velocity.normalize_or_zero(),
+                        ..active
+                    })
+                }
+                _ => Some(ActiveManoeuvre {
+                    kind,
+                    predator: threat.entity,
+                    elapsed: 0.0,
+                    position: threat.position,
+                    heading: threat.velocity.normalize_or_zero(),
+                }),
+            };
+        }
+
+        let Some(mut active) = evasion.active else {
+            continue;
+        };
+        active.elapsed += time.delta_seconds();
+
+        let profile = *evasion.profile(active.kind);
+        let Some(strength) = profile.strength(active.elapsed) else {
+            evasion.active = None;
+            continue;
+        };
+        evasion.active = Some(active);
+
+        // Blend the behaviour weights between the manoeuvre and the boid's own weights
+        scale.coherence *= 1.0 + (profile.coherence - 1.0) * strength;
+        scale.separation *= 1.0 + (profile.separation - 1.0) * strength;
+        scale.alignment *= 1.0 + (profile.alignment - 1.0) * strength;
+
+        let force = measure_manoeuvre(&evasion, &active, pos) * profile.force * strength;
+        if let Some(context) = context {
+            let closeness = 1.0 - (active.position.distance(pos) / evasion.range).min(1.0);
+            context.add_danger(active.position - pos, closeness * strength);
+            context.add_interest(force, force.length());
+        } else if force != Vec3::ZERO {
+            steer.add(priorities.evasion, force);
+        }
+    }
+}
+
+fn measure_manoeuvre(evasion: &Evasion, active: &ActiveManoeuvre, pos: Vec3) -> Vec3 {
+    let away = pos - active.position;
+
+    match active.kind {
+        Manoeuvre::FlashExpansion => away.normalize_or_zero(),
+        Manoeuvre::Fountain => {
+            let heading = if active.heading == Vec3::ZERO {
+                -away.normalize_or_zero()
+            } else {
+                active.heading
+            };
+            let ahead = away.dot(heading);
+
+            if ahead > 0.0 {
+                // Still in front of the predator, split sideways out of its path
+                let lateral = away - heading * ahead;
+                lateral.normalize_or_zero()
+            } else {
+                // Predator went past, regroup in its wake
+                let wake = active.position - heading * evasion.vacuole_radius;
+                (wake - pos).normalize_or_zero()
+            }
+        }
+        Manoeuvre::Vacuole => {
+            let dist = away.length();
+            if dist < evasion.vacuole_radius {
+                away.normalize_or_zero() * (evasion.vacuole_radius - dist) / evasion.vacuole_radius
+            } else {
+                Vec3::ZERO
+            }
+        }
+    }
+}
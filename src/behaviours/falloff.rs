@@ -0,0 +1,95 @@
+/// How strongly a neighbour counts depending on its distance
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Falloff {
+    /// Every neighbour within the radius counts fully
+    #[default]
+    Constant,
+    /// Fades from 1 next to the boid to 0 at the radius
+    Linear,
+    /// Full weight up to `softening`, then falls off with the square of the distance
+    InverseSquare {
+        softening: f32,
+    },
+    Gaussian {
+        sigma: f32,
+    },
+    /// Takes the distance as a fraction of the radius, from 0 to 1
+    Custom(fn(f32) -> f32),
+}
+
+impl Falloff {
+    /// Weight of a neighbour at `distance`, always 0 beyond `radius`
+    pub fn weight(&self, distance: f32, radius: f32) -> f32 {
+        if distance > radius || radius <= 0.0 {
+            return 0.0;
+        }
+
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => 1.0 - distance / radius,
+            Falloff::InverseSquare { softening } => {
+                if distance <= *softening {
+                    1.0
+                } else {
+                    (softening / distance).powi(2)
+                }
+            }
+            Falloff::Gaussian { sigma } => {
+                let sigma = sigma.max(f32::EPSILON);
+                (-(distance * distance) / (2.0 * sigma * sigma)).exp()
+            }
+            Falloff::Custom(curve) => curve(distance / radius).max(0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: [Falloff; 5] = [
+        Falloff::Constant,
+        Falloff::Linear,
+        Falloff::InverseSquare { softening: 1.0 },
+        Falloff::Gaussian { sigma: 2.0 },
+        Falloff::Custom(|t| 1.0 - t * t),
+    ];
+
+    #[test]
+    fn nothing_counts_beyond_the_radius() {
+        for falloff in ALL {
+            assert_eq!(falloff.weight(5.1, 5.0), 0.0);
+            assert_eq!(falloff.weight(1.0, 0.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn weights_never_grow_with_distance() {
+        for falloff in ALL {
+            let weights: Vec<f32> = (0..=10).map(|d| falloff.weight(d as f32, 10.0)).collect();
+            assert!(weights.windows(2).all(|w| w[1] <= w[0]), "{:?}", falloff);
+            assert!(
+                weights.iter().all(|w| (0.0..=1.0).contains(w)),
+                "{:?}",
+                falloff
+            );
+        }
+    }
+
+    #[test]
+    fn kernels_match_their_definitions() {
+        assert_eq!(Falloff::Constant.weight(9.0, 10.0), 1.0);
+        assert_eq!(Falloff::Linear.weight(2.5, 10.0), 0.75);
+        assert_eq!(Falloff::Linear.weight(10.0, 10.0), 0.0);
+
+        let inverse = Falloff::InverseSquare { softening: 2.0 };
+        assert_eq!(inverse.weight(1.0, 10.0), 1.0);
+        assert_eq!(inverse.weight(4.0, 10.0), 0.25);
+
+        let gaussian = Falloff::Gaussian { sigma: 1.0 };
+        assert_eq!(gaussian.weight(0.0, 10.0), 1.0);
+        assert!((gaussian.weight(1.0, 10.0) - (-0.5f32).exp()).abs() < 1e-6);
+
+        assert_eq!(Falloff::Custom(|t| t - 1.0).weight(5.0, 10.0), 0.0);
+    }
+}
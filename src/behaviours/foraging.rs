@@ -0,0 +1,165 @@
+use crate::behaviours::scale::FactorScale;
+use crate::context::SteeringOutput;
+use crate::energy::Energy;
+use crate::flock::SteeringPriorities;
+use crate::perception::Perception;
+use crate::physics::Velocity;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// A patch of food that is eaten by foragers and grows back over time
+#[derive(Component, Clone, Copy)]
+pub struct FoodSource {
+    pub amount: f32,
+    pub capacity: f32,
+    /// Food regrown per second
+    pub regrowth: f32,
+    /// Foragers closer than this are feeding
+    pub radius: f32,
+}
+
+impl Default for FoodSource {
+    fn default() -> Self {
+        Self {
+            amount: 100.0,
+            capacity: 100.0,
+            regrowth: 1.0,
+            radius: 5.0,
+        }
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Forager {
+    /// Attraction toward food within perception range
+    pub factor: f32,
+    /// Food eaten per second while feeding
+    pub feed_rate: f32,
+    /// Energy gained for each unit of food
+    pub energy_per_food: f32,
+    /// Fraction of its velocity a feeding boid keeps
+    pub feeding_speed: f32,
+    /// Separation multiplier while feeding
+    pub feeding_separation: f32,
+    /// The food source the boid is feeding from
+    pub feeding: Option<Entity>,
+}
+
+impl Default for Forager {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            feed_rate: 5.0,
+            energy_per_food: 1.0,
+            feeding_speed: 0.3,
+            feeding_separation: 2.0,
+            feeding: None,
+        }
+    }
+}
+
+/// Food eaten by each boid, to compare foraging efficiency across flock sizes
+#[derive(Resource, Default)]
+pub struct ForagingStats {
+    pub intake: HashMap<Entity, f32>,
+}
+
+impl ForagingStats {
+    pub fn total(&self) -> f32 {
+        self.intake.values().sum()
+    }
+
+    pub fn mean(&self) -> f32 {
+        if self.intake.is_empty() {
+            0.0
+        } else {
+            self.total() / self.intake.len() as f32
+        }
+    }
+}
+
+pub fn foraging_system(
+    mut query: Query<(
+        Entity,
+        &Transform,
+        &Velocity,
+        &Perception,
+        &mut Forager,
+        &mut FactorScale,
+        SteeringOutput,
+    )>,
+    mut sources: Query<(Entity, &Transform, &mut FoodSource)>,
+    mut energies: Query<&mut Energy>,
+    mut stats: ResMut<ForagingStats>,
+    time: Res<Time>,
+    priorities: Res<SteeringPriorities>,
+) {
+    for (entity, tf, vel, per, mut forager, mut scale, (steer, context)) in &mut query {
+        let pos = tf.translation;
+
+        // Closest source that still has food
+        let nearest = sources
+            .iter()
+            .filter(|(_, _, food)| food.amount > 0.0)
+            .map(|(e, food_tf, food)| (e, food_tf.translation, food.radius))
+            .filter(|(_, food_pos, _)| food_pos.distance(pos) <= per.range)
+            .min_by(|a, b| a.1.distance(pos).total_cmp(&b.1.distance(pos)));
+
+        let Some((source, food_pos, radius)) = nearest else {
+            forager.feeding = None;
+            continue;
+        };
+
+        if food_pos.distance(pos) > radius {
+            forager.feeding = None;
+
+            let force = (food_pos - pos) * forager.factor;
+            match context {
+                Some(context) => context.add_interest(force, force.length()),
+                None => steer.add(priorities.foraging, force),
+            }
+            continue;
+        }
+
+        forager.feeding = Some(source);
+        scale.separation *= forager.feeding_separation;
+
+        match context {
+            // Braking has no direction to be interested in, keep circling the food instead
+            Some(context) => context.add_interest(food_pos - pos, forager.factor),
+            None => {
+                // Brake toward the feeding speed
+                let force = -vel.vec * (1.0 - forager.feeding_speed) * forager.factor;
+                steer.add(priorities.foraging, force);
+            }
+        }
+
+        if let Ok((_, _, mut food)) = sources.get_mut(source) {
+            let eaten = (forager.feed_rate * time.delta_seconds()).min(food.amount);
+            food.amount -= eaten;
+            *stats.intake.entry(entity).or_insert(0.0) += eaten;
+
+            if let Ok(mut energy) = energies.get_mut(entity) {
+                energy.value = (energy.value + eaten * forager.energy_per_food).min(energy.max);
+            }
+        }
+    }
+}
+
+/// Forgets the intake of foragers that were despawned, so they no longer count in the stats
+pub fn foraging_stats_cleanup_system(
+    mut stats: ResMut<ForagingStats>,
+    mut removed: RemovedComponents<Forager>,
+) {
+    for entity in removed.iter() {
+        stats.intake.remove(&entity);
+    }
+}
+
+pub fn food_regrowth_system(mut query: Query<&mut FoodSource>, time: Res<Time>) {
+    for mut food in &mut query {
+        if food.amount < food.capacity {
+            food.amount = (food.amount + food.regrowth * time.delta_seconds()).min(food.capacity);
+        }
+    }
+}
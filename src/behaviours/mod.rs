@@ -1,37 +1,116 @@
-use crate::behaviours::alignment::alignment_system;
-use crate::behaviours::bounds::boundaries_system;
+use crate::behaviours::altitude::altitude_system;
+use crate::behaviours::bounds::{boundaries_system, boundary_response_system};
 use crate::behaviours::coherence::coherence_system;
+use crate::behaviours::evasion::evasion_system;
+use crate::behaviours::foraging::{
+    food_regrowth_system, foraging_stats_cleanup_system, foraging_system, ForagingStats,
+};
+use crate::behaviours::roosting::{
+    resting_system, roost_search_system, takeoff_system, RoostDisturbance,
+};
+use crate::behaviours::scale::factor_scale_reset_system;
 use crate::behaviours::separation::separation_system;
+use crate::behaviours::state::{behaviour_state_system, StateChanged};
+use crate::behaviours::unaligned::unaligned_avoidance_system;
 use crate::behaviours::velocity_adjust::desired_velocity_system;
+use crate::behaviours::zones::zone_system;
+use crate::physics::{force_application_system, velocity_system};
+use crate::species::InteractionMatrix;
 use crate::BoidStage;
 use bevy::prelude::*;
 
 pub mod alignment;
+pub mod altitude;
 pub mod avoidance;
 pub mod bounds;
 pub mod coherence;
+pub mod evasion;
+pub mod falloff;
+pub mod foraging;
+pub mod roosting;
+pub mod scale;
 pub mod separation;
+pub mod state;
+pub mod steering;
+pub mod unaligned;
 pub mod velocity_adjust;
+pub mod zones;
 
 pub use alignment::Alignment;
-pub use bounds::WorldBound;
+pub use altitude::{AltitudePreference, GroundSource};
+pub use avoidance::ObstacleAvoidancePlugin;
+pub use bounds::{BoundaryCrossing, BoundaryEvent, BoundarySpawner, WorldBound};
 pub use coherence::Coherence;
+pub use evasion::Evasion;
+pub use falloff::Falloff;
+pub use foraging::{FoodSource, Forager};
+pub use roosting::{Resting, Roosting};
+pub use scale::FactorScale;
 pub use separation::Separation;
+pub use state::BehaviourState;
+pub use steering::{SteeringBehaviour, SteeringBehaviourPlugin};
+pub use unaligned::UnalignedAvoidance;
 pub use velocity_adjust::DesiredVelocity;
+pub use zones::{Zone, ZoneFilter, ZoneResponse};
+
+/// Mutable access to every behaviour factor a boid may have
+pub type BehaviourFactors = (
+    Option<&'static mut Coherence>,
+    Option<&'static mut Separation>,
+    Option<&'static mut Alignment>,
+    Option<&'static mut WorldBound>,
+    Option<&'static mut avoidance::ObstacleAvoidance>,
+    Option<&'static mut DesiredVelocity>,
+);
 
 pub struct BoidsPlugin;
 
 impl Plugin for BoidsPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.init_resource::<InteractionMatrix>().add_systems(
             (
                 separation_system,
-                alignment_system,
+                unaligned_avoidance_system,
                 coherence_system,
                 desired_velocity_system,
                 boundaries_system,
+                altitude_system,
+                zone_system,
             )
                 .in_set(BoidStage::ForceCalculation),
         );
+
+        // Alignment runs through the same extension point as user-defined behaviours
+        app.add_plugin(SteeringBehaviourPlugin::<Alignment>::default());
+
+        // Reweighting happens before the forces are measured, and is reset once they are integrated
+        app.add_event::<StateChanged>()
+            .add_system(behaviour_state_system.before(BoidStage::ForceCalculation));
+
+        app.add_system(evasion_system.before(BoidStage::ForceCalculation))
+            .add_system(foraging_system.before(BoidStage::ForceCalculation))
+            .add_system(factor_scale_reset_system.in_set(BoidStage::ForceIntegration));
+
+        app.init_resource::<ForagingStats>()
+            .add_system(food_regrowth_system)
+            .add_system(foraging_stats_cleanup_system);
+
+        // Resting boids are pinned after the forces are applied, but before they move
+        app.add_event::<RoostDisturbance>()
+            .add_system(roost_search_system.before(BoidStage::ForceCalculation))
+            .add_systems(
+                (resting_system, takeoff_system)
+                    .chain()
+                    .in_set(BoidStage::ForceApplication)
+                    .after(force_application_system)
+                    .before(velocity_system),
+            );
+
+        // Hard boundaries act on where the boids moved to
+        app.add_event::<BoundaryEvent>().add_system(
+            boundary_response_system
+                .in_set(BoidStage::ForceApplication)
+                .after(velocity_system),
+        );
     }
 }
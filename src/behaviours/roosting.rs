@@ -0,0 +1,219 @@
+use crate::context::SteeringOutput;
+use crate::flock::{BoidsRules, LocalRules, SteeringPriorities};
+use crate::physics::{Acceleration, ColliderFilters, Velocity};
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum RoostState {
+    #[default]
+    Flying,
+    Approaching {
+        perch: Vec3,
+        normal: Vec3,
+    },
+    Resting,
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Roosting {
+    pub factor: f32,
+    /// How far from the boid a perch can be
+    pub search_range: f32,
+    /// The boid starts slowing down within this distance of the perch
+    pub arrive_radius: f32,
+    /// The boid settles once this close to the perch
+    pub settle_distance: f32,
+    /// Seconds of flight before looking for a perch
+    pub flight_time: f32,
+    /// Seconds of rest before taking off
+    pub rest_time: f32,
+    /// Seconds an approach may take before the perch is given up and the boid flies on
+    pub approach_time: f32,
+    /// Resting boids within this distance take off together
+    pub takeoff_radius: f32,
+    pub takeoff_speed: f32,
+    pub timer: f32,
+    pub state: RoostState,
+}
+
+impl Default for Roosting {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            search_range: 50.0,
+            arrive_radius: 10.0,
+            settle_distance: 0.5,
+            flight_time: 30.0,
+            rest_time: 10.0,
+            approach_time: 15.0,
+            takeoff_radius: 15.0,
+            takeoff_speed: 20.0,
+            timer: 0.0,
+            state: RoostState::Flying,
+        }
+    }
+}
+
+/// A boid settled on a perch. Resting boids do not move and are left out of the spatial partition.
+#[derive(Component, Clone, Copy)]
+pub struct Resting {
+    pub perch: Vec3,
+    pub normal: Vec3,
+}
+
+/// Sent to scare resting boids within a radius into the air
+pub struct RoostDisturbance {
+    pub position: Vec3,
+    pub radius: f32,
+}
+
+pub fn roost_search_system(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &Transform, &Velocity, &mut Roosting, SteeringOutput),
+        Without<Resting>,
+    >,
+    perches: PerchSearch,
+    locals: Query<&LocalRules>,
+    rules: Res<BoidsRules>,
+    time: Res<Time>,
+    priorities: Res<SteeringPriorities>,
+) {
+    for (entity, tf, vel, mut roost, (steer, context)) in &mut query {
+        let pos = tf.translation;
+
+        match roost.state {
+            RoostState::Flying => {
+                roost.timer += time.delta_seconds();
+                if roost.timer < roost.flight_time {
+                    continue;
+                }
+
+                roost.timer = 0.0;
+                if let Some((perch, normal)) = perches.find(entity, pos, roost.search_range) {
+                    roost.state = RoostState::Approaching { perch, normal };
+                }
+            }
+            RoostState::Approaching { perch, normal } => {
+                // The perch may be out of reach, rejoin the flock instead of circling forever
+                roost.timer += time.delta_seconds();
+                if roost.timer >= roost.approach_time {
+                    roost.state = RoostState::Flying;
+                    roost.timer = 0.0;
+                    continue;
+                }
+
+                let to_perch = perch - pos;
+                let distance = to_perch.length();
+
+                if distance <= roost.settle_distance {
+                    roost.state = RoostState::Resting;
+                    roost.timer = 0.0;
+                    commands.entity(entity).insert(Resting { perch, normal });
+                    continue;
+                }
+
+                // Arrive steering, full speed far away and slowing down near the perch
+                let rules = rules.local(locals.get(entity).ok());
+                let speed = rules.desired_speed * (distance / roost.arrive_radius).min(1.0);
+                let desired = to_perch / distance * speed;
+                let force = (desired - vel.vec) * roost.factor;
+
+                match context {
+                    Some(context) => context.add_interest(force, force.length()),
+                    None => steer.add(priorities.roosting, force),
+                }
+            }
+            RoostState::Resting => {}
+        }
+    }
+}
+
+/// Everything needed to look for a perch among the obstacles a boid sees
+#[derive(SystemParam)]
+pub struct PerchSearch<'w, 's> {
+    rapier: Res<'w, RapierContext>,
+    filters: Res<'w, ColliderFilters>,
+    local_filters: Query<'w, 's, &'static ColliderFilters>,
+}
+
+impl PerchSearch<'_, '_> {
+    /// Looks straight down for a perch, falling back to the nearest surface in range
+    fn find(&self, entity: Entity, pos: Vec3, range: f32) -> Option<(Vec3, Vec3)> {
+        let filter = self
+            .filters
+            .local(self.local_filters.get(entity).ok())
+            .obstacle_filter(entity);
+
+        if let Some((_, hit)) =
+            self.rapier
+                .cast_ray_and_get_normal(pos, Vec3::NEG_Y, range, true, filter)
+        {
+            return Some((hit.point, hit.normal));
+        }
+
+        let (_, projection) = self.rapier.project_point(pos, false, filter)?;
+        let offset = pos - projection.point;
+        if projection.is_inside || offset.length() > range {
+            return None;
+        }
+        Some((projection.point, offset.normalize_or_zero()))
+    }
+}
+
+/// Pins resting boids to their perch, runs between the force application and the velocity
+pub fn resting_system(
+    mut query: Query<(
+        &mut Transform,
+        &mut Velocity,
+        &mut Acceleration,
+        &mut Roosting,
+        &Resting,
+    )>,
+    time: Res<Time>,
+) {
+    for (mut tf, mut vel, mut acc, mut roost, rest) in &mut query {
+        vel.vec = Vec3::ZERO;
+        acc.vec = Vec3::ZERO;
+        tf.translation = rest.perch;
+        roost.timer += time.delta_seconds();
+    }
+}
+
+/// Takes resting boids off as a group, once one has rested long enough or the roost is disturbed
+pub fn takeoff_system(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Velocity, &mut Roosting, &Resting)>,
+    mut disturbances: EventReader<RoostDisturbance>,
+) {
+    let mut triggers: Vec<(Vec3, f32)> = disturbances
+        .iter()
+        .map(|d| (d.position, d.radius))
+        .collect();
+
+    triggers.extend(
+        query
+            .iter()
+            .filter(|(_, _, roost, _)| roost.timer >= roost.rest_time)
+            .map(|(_, _, roost, rest)| (rest.perch, roost.takeoff_radius)),
+    );
+
+    if triggers.is_empty() {
+        return;
+    }
+
+    for (entity, mut vel, mut roost, rest) in &mut query {
+        let disturbed = triggers
+            .iter()
+            .any(|(pos, radius)| pos.distance(rest.perch) <= *radius);
+
+        if disturbed {
+            roost.state = RoostState::Flying;
+            roost.timer = 0.0;
+            vel.vec = rest.normal * roost.takeoff_speed;
+            commands.entity(entity).remove::<Resting>();
+        }
+    }
+}
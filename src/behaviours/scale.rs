@@ -0,0 +1,28 @@
+use bevy::prelude::*;
+
+/// Temporary multipliers applied on top of the flocking behaviour factors.
+///
+/// Systems that want to reweight a behaviour for a frame multiply into the scale before
+/// `BoidStage::ForceCalculation`. The scale is reset once the forces have been integrated.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct FactorScale {
+    pub coherence: f32,
+    pub separation: f32,
+    pub alignment: f32,
+}
+
+impl Default for FactorScale {
+    fn default() -> Self {
+        Self {
+            coherence: 1.0,
+            separation: 1.0,
+            alignment: 1.0,
+        }
+    }
+}
+
+pub fn factor_scale_reset_system(mut query: Query<&mut FactorScale>) {
+    for mut scale in &mut query {
+        *scale = FactorScale::default();
+    }
+}
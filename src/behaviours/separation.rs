@@ -1,33 +1,76 @@
-use crate::flock::SteeringPressure;
+use crate::behaviours::falloff::Falloff;
+use crate::behaviours::scale::FactorScale;
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
 use crate::perception::Perception;
+use crate::species::SpeciesLookup;
+use crate::surface::SurfaceLookup;
 use bevy::prelude::*;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone, Copy)]
 pub struct Separation {
     pub factor: f32,
     pub distance: f32,
+    /// Interaction radius, None uses the `Perception` range
+    pub radius: Option<f32>,
+    pub falloff: Falloff,
 }
 
 pub fn separation_system(
-    query: Query<(Entity, &Perception, &Separation, &SteeringPressure)>,
+    query: Query<(
+        Entity,
+        &Perception,
+        &Separation,
+        SteeringOutput,
+        Option<&FactorScale>,
+    )>,
     boids: Query<&Transform>,
+    species: SpeciesLookup,
+    surfaces: SurfaceLookup,
+    priorities: Res<SteeringPriorities>,
 ) {
-    for (entity, per, sep, steer) in query.iter() {
+    for (entity, per, sep, (steer, context), scale) in query.iter() {
         // Use data from spatial hash instead of all behaviours
         let neighbours = &per.list;
-        let force = measure_separation(entity, &boids, neighbours, sep.distance) * sep.factor;
-        let mut vec = steer.lock.write().unwrap();
-        *vec += force;
+        let factor = sep.factor * scale.map_or(1.0, |s| s.separation);
+        let radius = sep.radius.unwrap_or(per.range);
+
+        if let Some(context) = context {
+            // Every neighbour is a danger of its own, so that neighbours on both sides do not cancel
+            let pos = boids.get(entity).unwrap().translation;
+            for &e in neighbours.iter().filter(|&&e| e != entity) {
+                let offset = surfaces.offset(entity, pos, boids.get(e).unwrap().translation);
+                let distance = offset.length();
+                let closeness = 1.0 - (distance / radius).min(1.0);
+                let weight = sep.falloff.weight(distance, radius) * sep.distance;
+                let weight = closeness * weight * factor * species.weights(entity, e).separation;
+
+                // A repelling species pulls the boid in instead
+                if weight < 0.0 {
+                    context.add_interest(offset, -weight);
+                } else {
+                    context.add_danger(offset, weight);
+                }
+            }
+            continue;
+        }
+
+        let force =
+            measure_separation(entity, &boids, &species, &surfaces, neighbours, sep, radius)
+                * factor;
+        steer.add(priorities.separation, force);
     }
 }
 
 pub fn measure_separation(
     entity: Entity,
     query: &Query<&Transform>,
+    species: &SpeciesLookup,
+    surfaces: &SurfaceLookup,
     neighbours: &Vec<Entity>,
-    dist: f32,
+    sep: &Separation,
+    radius: f32,
 ) -> Vec3 {
-    let mut count = 0;
     let local_tf = query.get(entity).unwrap().translation;
 
     let result = neighbours
@@ -35,11 +78,12 @@ pub fn measure_separation(
         // Exclude our current boid
         .filter(|&&e| entity != e)
         // Get all translations
-        .map(|&e| query.get(e).unwrap().translation)
-        .map(|v| {
-            count += 1;
-            let sep = -1.0 * (v - local_tf);
-            sep / sep.length() * dist
+        .map(|&e| (e, query.get(e).unwrap().translation))
+        .map(|(e, v)| {
+            let away = -1.0 * surfaces.offset(entity, local_tf, v);
+            let weight = sep.falloff.weight(away.length(), radius);
+            let weight = weight * species.weights(entity, e).separation;
+            away.normalize_or_zero() * sep.distance * weight
         })
         .sum();
 
@@ -0,0 +1,272 @@
+use crate::alarm::{Alarm, Startle};
+use crate::behaviours::foraging::Forager;
+use crate::behaviours::roosting::Resting;
+use crate::behaviours::BehaviourFactors;
+use crate::energy::Energy;
+use crate::physics::Velocity;
+use crate::predator::{nearest_threat, Predator};
+use bevy::ecs::query::QueryItem;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+/// Factors a state gives to each behaviour, None leaves the behaviour untouched
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WeightProfile {
+    pub coherence: Option<f32>,
+    pub separation: Option<f32>,
+    pub alignment: Option<f32>,
+    pub world_bound: Option<f32>,
+    pub avoidance: Option<f32>,
+    pub desired_velocity: Option<f32>,
+}
+
+impl WeightProfile {
+    fn weights(&self) -> [Option<f32>; 6] {
+        [
+            self.coherence,
+            self.separation,
+            self.alignment,
+            self.world_bound,
+            self.avoidance,
+            self.desired_velocity,
+        ]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Condition {
+    PredatorWithin(f32),
+    PredatorBeyond(f32),
+    /// Fraction of the energy reserve
+    EnergyBelow(f32),
+    EnergyAbove(f32),
+    /// Seconds spent in the current state
+    After(f32),
+    Startled,
+    Feeding,
+    Resting,
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+/// What the conditions can observe about a boid
+pub struct ConditionContext {
+    pub threat: Option<f32>,
+    pub energy: Option<f32>,
+    pub startled: bool,
+    pub feeding: bool,
+    pub resting: bool,
+    pub elapsed: f32,
+}
+
+impl Condition {
+    pub fn is_met(&self, ctx: &ConditionContext) -> bool {
+        match self {
+            Condition::PredatorWithin(d) => matches!(ctx.threat, Some(t) if t <= *d),
+            Condition::PredatorBeyond(d) => !matches!(ctx.threat, Some(t) if t <= *d),
+            Condition::EnergyBelow(r) => matches!(ctx.energy, Some(e) if e < *r),
+            Condition::EnergyAbove(r) => matches!(ctx.energy, Some(e) if e > *r),
+            Condition::After(t) => ctx.elapsed >= *t,
+            Condition::Startled => ctx.startled,
+            Condition::Feeding => ctx.feeding,
+            Condition::Resting => ctx.resting,
+            Condition::Not(c) => !c.is_met(ctx),
+            Condition::All(list) => list.iter().all(|c| c.is_met(ctx)),
+            Condition::Any(list) => list.iter().any(|c| c.is_met(ctx)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Transition {
+    /// State the transition leaves from, None for any state
+    pub from: Option<String>,
+    pub to: String,
+    pub condition: Condition,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Blend {
+    from: [Option<f32>; 6],
+    progress: f32,
+}
+
+/// Switches a boid between named weight profiles.
+///
+/// Transitions are checked in order and the first one met wins. The behaviour factors are then
+/// blended from their current value to the new profile over `blend_time` seconds.
+///
+/// Profiles set the factors outright, so they replace whatever evolution or lifecycle mutations
+/// wrote. Leave a factor at None in every profile to keep it inherited.
+#[derive(Component, Clone, Debug)]
+pub struct BehaviourState {
+    pub profiles: HashMap<String, WeightProfile>,
+    pub transitions: Vec<Transition>,
+    pub current: String,
+    /// Seconds spent in the current state
+    pub elapsed: f32,
+    pub blend_time: f32,
+    blend: Option<Blend>,
+}
+
+impl BehaviourState {
+    pub fn new(initial: &str, blend_time: f32) -> Self {
+        Self {
+            profiles: HashMap::default(),
+            transitions: Vec::new(),
+            current: initial.to_string(),
+            elapsed: 0.0,
+            blend_time,
+            blend: None,
+        }
+    }
+
+    pub fn with_profile(mut self, name: &str, profile: WeightProfile) -> Self {
+        self.profiles.insert(name.to_string(), profile);
+        self
+    }
+
+    pub fn with_transition(mut self, from: Option<&str>, to: &str, condition: Condition) -> Self {
+        self.transitions.push(Transition {
+            from: from.map(str::to_string),
+            to: to.to_string(),
+            condition,
+        });
+        self
+    }
+
+    pub fn is_blending(&self) -> bool {
+        self.blend.is_some()
+    }
+}
+
+/// Sent when a boid switches to another state
+pub struct StateChanged {
+    pub entity: Entity,
+    pub from: String,
+    pub to: String,
+}
+
+/// Everything the transition conditions read from other components
+#[derive(SystemParam)]
+pub struct ConditionQueries<'w, 's> {
+    predators:
+        Query<'w, 's, (Entity, &'static Transform, Option<&'static Velocity>), With<Predator>>,
+    energies: Query<'w, 's, &'static Energy>,
+    startles: Query<'w, 's, (&'static Startle, &'static Alarm)>,
+    foragers: Query<'w, 's, &'static Forager>,
+    resting: Query<'w, 's, (), With<Resting>>,
+}
+
+impl ConditionQueries<'_, '_> {
+    fn context(&self, entity: Entity, position: Vec3, elapsed: f32) -> ConditionContext {
+        ConditionContext {
+            threat: nearest_threat(position, f32::INFINITY, &self.predators).map(|t| t.distance),
+            energy: self.energies.get(entity).ok().map(|e| e.ratio()),
+            startled: matches!(self.startles.get(entity), Ok((s, a)) if s.is_startled(a)),
+            feeding: matches!(self.foragers.get(entity), Ok(f) if f.feeding.is_some()),
+            resting: self.resting.contains(entity),
+            elapsed,
+        }
+    }
+}
+
+pub fn behaviour_state_system(
+    mut query: Query<(Entity, &Transform, &mut BehaviourState, BehaviourFactors)>,
+    conditions: ConditionQueries,
+    mut events: EventWriter<StateChanged>,
+    time: Res<Time>,
+) {
+    for (entity, tf, mut state, mut factors) in &mut query {
+        state.elapsed += time.delta_seconds();
+        let ctx = conditions.context(entity, tf.translation, state.elapsed);
+
+        let next = state
+            .transitions
+            .iter()
+            .filter(|t| t.to != state.current)
+            .filter(|t| t.from.is_none() || t.from.as_ref() == Some(&state.current))
+            .find(|t| t.condition.is_met(&ctx))
+            .map(|t| t.to.clone());
+
+        if let Some(next) = next {
+            let from = std::mem::replace(&mut state.current, next.clone());
+            state.elapsed = 0.0;
+            state.blend = Some(Blend {
+                from: read_factors(&factors),
+                progress: 0.0,
+            });
+            events.send(StateChanged {
+                entity,
+                from,
+                to: next,
+            });
+        }
+
+        let Some(profile) = state.profiles.get(&state.current).copied() else {
+            continue;
+        };
+
+        let target = profile.weights();
+        let weights = match state.blend {
+            Some(mut blend) => {
+                blend.progress += time.delta_seconds() / state.blend_time.max(f32::EPSILON);
+                let t = blend.progress.min(1.0);
+                let t = t * t * (3.0 - 2.0 * t);
+                state.blend = if blend.progress >= 1.0 {
+                    None
+                } else {
+                    Some(blend)
+                };
+
+                let mut weights = [None; 6];
+                for i in 0..weights.len() {
+                    weights[i] = match (blend.from[i], target[i]) {
+                        (Some(a), Some(b)) => Some(a + (b - a) * t),
+                        (_, b) => b,
+                    };
+                }
+                weights
+            }
+            None => target,
+        };
+
+        write_factors(&mut factors, &weights);
+    }
+}
+
+fn read_factors(factors: &QueryItem<BehaviourFactors>) -> [Option<f32>; 6] {
+    let (coh, sep, ali, bound, avoid, des) = factors;
+    [
+        coh.as_ref().map(|c| c.factor),
+        sep.as_ref().map(|c| c.factor),
+        ali.as_ref().map(|c| c.factor),
+        bound.as_ref().map(|c| c.factor),
+        avoid.as_ref().map(|c| c.factor),
+        des.as_ref().map(|c| c.factor),
+    ]
+}
+
+fn write_factors(factors: &mut QueryItem<BehaviourFactors>, weights: &[Option<f32>; 6]) {
+    let (coh, sep, ali, bound, avoid, des) = factors;
+    if let (Some(c), Some(w)) = (coh, weights[0]) {
+        c.factor = w;
+    }
+    if let (Some(c), Some(w)) = (sep, weights[1]) {
+        c.factor = w;
+    }
+    if let (Some(c), Some(w)) = (ali, weights[2]) {
+        c.factor = w;
+    }
+    if let (Some(c), Some(w)) = (bound, weights[3]) {
+        c.factor = w;
+    }
+    if let (Some(c), Some(w)) = (avoid, weights[4]) {
+        c.factor = w;
+    }
+    if let (Some(c), Some(w)) = (des, weights[5]) {
+        c.factor = w;
+    }
+}
@@ -0,0 +1,194 @@
+use crate::behaviours::falloff::Falloff;
+use crate::behaviours::scale::FactorScale;
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
+use crate::perception::Perception;
+use crate::physics::Velocity;
+use crate::species::{InteractionWeights, SpeciesLookup};
+use crate::surface::SurfaceLookup;
+use crate::BoidStage;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use std::marker::PhantomData;
+
+/// The boid a behaviour is steering
+#[derive(Clone, Copy, Debug)]
+pub struct Actor {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    /// Multipliers set for this frame, neutral when the boid has no `FactorScale`
+    pub scale: FactorScale,
+}
+
+/// A perceived boid within the behaviour's radius
+#[derive(Clone, Copy, Debug)]
+pub struct Neighbour {
+    pub entity: Entity,
+    pub position: Vec3,
+    /// In the actor's tangent plane when it is bound to a surface
+    pub velocity: Vec3,
+    /// From the actor to the neighbour, along the actor's surface if it has one
+    pub offset: Vec3,
+    pub distance: f32,
+    /// Weight given by the behaviour's falloff
+    pub weight: f32,
+    /// How the actor's species reacts to the neighbour's
+    pub species: InteractionWeights,
+}
+
+/// A steering behaviour registered through `SteeringBehaviourPlugin`.
+///
+/// The plugin gathers the neighbours within `radius`, weights them by `falloff`, scales the
+/// returned force by `factor` and writes it to the boid's `SteeringPressure` at `priority`,
+/// or as interest into its `ContextSteering` when it has one.
+pub trait SteeringBehaviour: Component {
+    fn steer(&self, actor: &Actor, neighbours: &[Neighbour]) -> Vec3;
+
+    fn factor(&self) -> f32 {
+        1.0
+    }
+
+    /// Interaction radius, None uses the `Perception` range
+    fn radius(&self) -> Option<f32> {
+        None
+    }
+
+    fn falloff(&self) -> Falloff {
+        Falloff::Constant
+    }
+
+    /// Lower values are served first, the built-in behaviours use `SteeringPriorities`
+    fn priority(&self, _priorities: &SteeringPriorities) -> u8 {
+        10
+    }
+
+    fn enabled(&self) -> bool {
+        true
+    }
+}
+
+/// Settings shared by every boid with the behaviour `T`
+#[derive(Resource)]
+pub struct BehaviourSettings<T> {
+    pub enabled: bool,
+    /// Record the force of each boid in `SteeringDebug`
+    pub capture: bool,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for BehaviourSettings<T> {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            capture: false,
+            marker: PhantomData,
+        }
+    }
+}
+
+/// Last force produced by the behaviour `T` for each boid, filled while capturing
+#[derive(Resource)]
+pub struct SteeringDebug<T> {
+    pub forces: HashMap<Entity, Vec3>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for SteeringDebug<T> {
+    fn default() -> Self {
+        Self {
+            forces: HashMap::default(),
+            marker: PhantomData,
+        }
+    }
+}
+
+pub struct SteeringBehaviourPlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for SteeringBehaviourPlugin<T> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T: SteeringBehaviour> Plugin for SteeringBehaviourPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BehaviourSettings<T>>()
+            .init_resource::<SteeringDebug<T>>()
+            .add_system(steering_behaviour_system::<T>.in_set(BoidStage::ForceCalculation));
+    }
+}
+
+pub fn steering_behaviour_system<T: SteeringBehaviour>(
+    query: Query<(
+        Entity,
+        &Perception,
+        &T,
+        SteeringOutput,
+        Option<&FactorScale>,
+    )>,
+    boids: Query<(&Transform, Option<&Velocity>)>,
+    lookups: (SpeciesLookup, SurfaceLookup),
+    priorities: Res<SteeringPriorities>,
+    settings: Res<BehaviourSettings<T>>,
+    mut debug: ResMut<SteeringDebug<T>>,
+) {
+    // Boids that stopped steering or despawned must not keep a stale force
+    debug.forces.clear();
+    if !settings.enabled {
+        return;
+    }
+
+    let (species, surfaces) = lookups;
+    let mut neighbours = Vec::new();
+    for (entity, per, behaviour, (steer, context), scale) in &query {
+        if !behaviour.enabled() {
+            continue;
+        }
+
+        let Ok((tf, vel)) = boids.get(entity) else {
+            continue;
+        };
+        let actor = Actor {
+            entity,
+            position: tf.translation,
+            velocity: vel.map_or(Vec3::ZERO, |v| v.vec),
+            scale: scale.copied().unwrap_or_default(),
+        };
+
+        let radius = behaviour.radius().unwrap_or(per.range);
+        let falloff = behaviour.falloff();
+
+        neighbours.clear();
+        neighbours.extend(
+            per.list
+                .iter()
+                .filter(|&&e| e != entity)
+                .filter_map(|&e| boids.get(e).ok().map(|(tf, vel)| (e, tf, vel)))
+                .map(|(e, tf, vel)| {
+                    let offset = surfaces.offset(entity, actor.position, tf.translation);
+                    let distance = offset.length();
+                    Neighbour {
+                        entity: e,
+                        position: tf.translation,
+                        velocity: surfaces.tangent(entity, vel.map_or(Vec3::ZERO, |v| v.vec)),
+                        offset,
+                        distance,
+                        weight: falloff.weight(distance, radius),
+                        species: species.weights(entity, e),
+                    }
+                })
+                .filter(|n| n.weight > 0.0),
+        );
+
+        let force = behaviour.steer(&actor, &neighbours) * behaviour.factor();
+        match context {
+            Some(context) => context.add_interest(force, force.length()),
+            None => steer.add(behaviour.priority(&priorities), force),
+        }
+
+        if settings.capture {
+            debug.forces.insert(entity, force);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use crate::context::SteeringOutput;
+use crate::flock::SteeringPriorities;
+use crate::perception::Perception;
+use crate::physics::Velocity;
+use bevy::prelude::*;
+
+/// Reynolds' unaligned collision avoidance: predicts the closest approach to each neighbour and
+/// steers sideways away from the most imminent one only
+#[derive(Component, Clone, Copy)]
+pub struct UnalignedAvoidance {
+    pub factor: f32,
+    /// Neighbours predicted to come closer than twice this are threats
+    pub radius: f32,
+    /// Seconds ahead approaches are predicted
+    pub horizon: f32,
+}
+
+impl Default for UnalignedAvoidance {
+    fn default() -> Self {
+        Self {
+            factor: 1.0,
+            radius: 1.0,
+            horizon: 2.0,
+        }
+    }
+}
+
+/// Closest approach to the most imminent neighbour
+struct Approach {
+    time: f32,
+    /// Our predicted position minus theirs at that time
+    offset: Vec3,
+    /// Their predicted position at that time
+    position: Vec3,
+}
+
+pub fn unaligned_avoidance_system(
+    query: Query<(Entity, &Perception, &UnalignedAvoidance, SteeringOutput)>,
+    boids: Query<(&Transform, &Velocity)>,
+    priorities: Res<SteeringPriorities>,
+) {
+    for (entity, per, avoid, (steer, context)) in &query {
+        let Ok((tf, vel)) = boids.get(entity) else {
+            continue;
+        };
+        let (pos, vel) = (tf.translation, vel.vec);
+
+        let Some(threat) = most_imminent(entity, pos, vel, &per.list, &boids, avoid) else {
+            continue;
+        };
+
+        // Sideways relative to our own heading, so crossing streams slide past each other
+        let forward = vel.normalize_or_zero();
+        let lateral = threat.offset - forward * threat.offset.dot(forward);
+        let lateral = lateral.try_normalize().unwrap_or_else(|| {
+            // Dead on collision course, pick a side in the plane the boids fly in
+            forward
+                .cross(Vec3::Z)
+                .try_normalize()
+                .unwrap_or_else(|| forward.any_orthonormal_vector())
+        });
+
+        let urgency = 1.0 - threat.time / avoid.horizon;
+        match context {
+            Some(context) => {
+                context.add_danger(threat.position - pos, urgency);
+                context.add_interest(lateral, urgency * avoid.factor);
+            }
+            None => steer.add(priorities.unaligned, lateral * urgency * avoid.factor),
+        }
+    }
+}
+
+fn most_imminent(
+    entity: Entity,
+    pos: Vec3,
+    vel: Vec3,
+    neighbours: &[Entity],
+    boids: &Query<(&Transform, &Velocity)>,
+    avoid: &UnalignedAvoidance,
+) -> Option<Approach> {
+    let threshold = 2.0 * avoid.radius;
+
+    neighbours
+        .iter()
+        .filter(|&&e| e != entity)
+        .filter_map(|&e| boids.get(e).ok())
+        .filter_map(|(other_tf, other_vel)| {
+            let relative_position = other_tf.translation - pos;
+            let relative_velocity = other_vel.vec - vel;
+            let speed_sq = relative_velocity.length_squared();
+            if speed_sq <= f32::EPSILON {
+                return None;
+            }
+
+            let time = -relative_position.dot(relative_velocity) / speed_sq;
+            if !(0.0..avoid.horizon).contains(&time) {
+                return None;
+            }
+
+            let ours = pos + vel * time;
+            let theirs = other_tf.translation + other_vel.vec * time;
+            let offset = ours - theirs;
+            (offset.length() < threshold).then_some(Approach {
+                time,
+                offset,
+                position: theirs,
+            })
+        })
+        .min_by(|a, b| a.time.total_cmp(&b.time))
+}
@@ -1,24 +1,34 @@
-use crate::flock::{BoidsRules, SteeringPressure};
+use crate::context::SteeringOutput;
+use crate::flock::{BoidsRules, LocalRules, SteeringPriorities};
 use crate::physics::Velocity;
 use bevy::prelude::*;
 
-#[derive(Component, Default)]
+#[derive(Component, Default, Clone, Copy)]
 pub struct DesiredVelocity {
     pub factor: f32,
 }
 
 pub fn desired_velocity_system(
-    query: Query<(&Velocity, &DesiredVelocity, &SteeringPressure)>,
+    query: Query<(
+        &Velocity,
+        &DesiredVelocity,
+        SteeringOutput,
+        Option<&LocalRules>,
+    )>,
     rules: Res<BoidsRules>,
+    priorities: Res<SteeringPriorities>,
 ) {
-    for (vel, des, steer) in &query {
-        let delta_vel = rules.desired_speed - vel.vec.length();
+    for (vel, des, (steer, context), local) in &query {
+        let delta_vel = rules.local(local).desired_speed - vel.vec.length();
         let unit_vel = vel.vec / vel.vec.length();
 
         if !unit_vel.is_nan() {
-            let force = unit_vel * delta_vel;
-            let mut vec = steer.lock.write().unwrap();
-            *vec += force * des.factor;
+            let force = unit_vel * delta_vel * des.factor;
+            match context {
+                // Keep on going the same way, the resolver takes care of the speed
+                Some(context) => context.add_interest(unit_vel, des.factor),
+                None => steer.add(priorities.desired_velocity, force),
+            }
         }
     }
 }
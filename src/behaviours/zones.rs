@@ -0,0 +1,91 @@
+use crate::behaviours::falloff::Falloff;
+use crate::context::SteeringOutput;
+use crate::flock::{FlockId, SteeringPriorities};
+use crate::species::Species;
+use bevy::prelude::*;
+
+/// Which boids a zone acts on
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ZoneFilter {
+    #[default]
+    All,
+    Species(usize),
+    Flock(u32),
+}
+
+/// A soft influence placed in the world, pulling boids in or pushing them away
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Zone {
+    /// Positive values attract, negative values repel
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: Falloff,
+    pub filter: ZoneFilter,
+}
+
+impl Zone {
+    pub fn attractor(strength: f32, radius: f32) -> Self {
+        Self {
+            strength: strength.abs(),
+            radius,
+            falloff: Falloff::Linear,
+            filter: ZoneFilter::All,
+        }
+    }
+
+    pub fn repulsor(strength: f32, radius: f32) -> Self {
+        Self {
+            strength: -strength.abs(),
+            ..Self::attractor(strength, radius)
+        }
+    }
+
+    pub fn affects(&self, species: Option<&Species>, flock: Option<&FlockId>) -> bool {
+        match self.filter {
+            ZoneFilter::All => true,
+            ZoneFilter::Species(s) => species.copied().unwrap_or_default().0 == s,
+            ZoneFilter::Flock(f) => matches!(flock, Some(id) if id.0 == f),
+        }
+    }
+}
+
+/// Makes a boid react to zones
+#[derive(Component, Default, Clone, Copy)]
+pub struct ZoneResponse {
+    pub factor: f32,
+}
+
+pub fn zone_system(
+    query: Query<(Entity, &Transform, &ZoneResponse, SteeringOutput)>,
+    tags: Query<(Option<&Species>, Option<&FlockId>)>,
+    zones: Query<(&Transform, &Zone)>,
+    priorities: Res<SteeringPriorities>,
+) {
+    for (entity, tf, response, (steer, context)) in &query {
+        let pos = tf.translation;
+        let (species, flock) = tags.get(entity).unwrap_or((None, None));
+
+        for (zone_tf, zone) in &zones {
+            if !zone.affects(species, flock) {
+                continue;
+            }
+
+            let offset = zone_tf.translation - pos;
+            let weight = zone.falloff.weight(offset.length(), zone.radius);
+            if weight <= 0.0 {
+                continue;
+            }
+
+            let weight = weight * response.factor;
+            match context {
+                // Repulsors are dangers, the closer the boid the less it dares to go that way
+                Some(context) if zone.strength < 0.0 => context.add_danger(offset, weight),
+                Some(context) => context.add_interest(offset, zone.strength * weight),
+                None => {
+                    let force = offset.normalize_or_zero() * zone.strength * weight;
+                    steer.add(priorities.zones, force);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::physics::{velocity_system, ColliderFilters, Velocity};
+use crate::BoidStage;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Resolution {
+    /// Stops at the surface and keeps only the velocity along it
+    Slide,
+    /// Bounces off the surface, keeping `restitution` of the speed into it
+    Reflect { restitution: f32 },
+}
+
+/// Stops a boid from moving through colliders, whatever its steering does
+#[derive(Component, Clone, Copy, Debug)]
+pub struct NonPenetration {
+    pub radius: f32,
+    pub resolution: Resolution,
+    /// Distance kept between the boid and the surface it is stopped at
+    pub skin: f32,
+}
+
+impl Default for NonPenetration {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            resolution: Resolution::Slide,
+            skin: 0.01,
+        }
+    }
+}
+
+/// Sent when a boid's movement was stopped by a collider
+pub struct PenetrationEvent {
+    pub boid: Entity,
+    pub obstacle: Entity,
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+pub struct NonPenetrationPlugin;
+
+impl Plugin for NonPenetrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PenetrationEvent>().add_system(
+            non_penetration_system
+                .in_set(BoidStage::ForceApplication)
+                .after(velocity_system),
+        );
+    }
+}
+
+/// Sweeps each boid from where it was before `velocity_system` moved it to where it is now,
+/// and resolves the first collider crossed on the way
+pub fn non_penetration_system(
+    mut query: Query<(
+        Entity,
+        &mut Transform,
+        &mut Velocity,
+        &NonPenetration,
+        Option<&ColliderFilters>,
+    )>,
+    rapier: Res<RapierContext>,
+    filters: Res<ColliderFilters>,
+    time: Res<Time>,
+    mut events: EventWriter<PenetrationEvent>,
+) {
+    let delta = time.delta_seconds();
+
+    for (entity, mut tf, mut vel, solid, local) in &mut query {
+        let travel = vel.vec * delta;
+        let distance = travel.length();
+        if distance <= f32::EPSILON {
+            continue;
+        }
+
+        let filter = filters.local(local).obstacle_filter(entity);
+        let start = tf.translation - travel;
+        let direction = travel / distance;
+        let shape = Collider::ball(solid.radius);
+
+        let Some((obstacle, hit)) =
+            rapier.cast_shape(start, Rot::default(), direction, &shape, distance, filter)
+        else {
+            continue;
+        };
+
+        let (point, normal) = if hit.status == TOIStatus::Penetrating {
+            // Already inside at the start of the frame, push out to the closest surface
+            let Some((_, projection)) = rapier.project_point(start, false, filter) else {
+                continue;
+            };
+            let outward = if projection.is_inside {
+                projection.point - start
+            } else {
+                start - projection.point
+            };
+            let normal = outward.normalize_or_zero();
+            tf.translation = projection.point + normal * (solid.radius + solid.skin);
+            (projection.point, normal)
+        } else {
+            // The witness and normal are on the collider hit, in world space
+            let normal = hit.normal1.normalize_or_zero();
+            tf.translation = start + direction * (hit.toi - solid.skin).max(0.0);
+            (hit.witness1, normal)
+        };
+
+        let into = vel.vec.dot(normal);
+        if into < 0.0 {
+            vel.vec = match solid.resolution {
+                Resolution::Slide => vel.vec - normal * into,
+                Resolution::Reflect { restitution } => {
+                    vel.vec - normal * into * (1.0 + restitution)
+                }
+            };
+        }
+
+        events.send(PenetrationEvent {
+            boid: entity,
+            obstacle,
+            point,
+            normal,
+        });
+    }
+}
@@ -0,0 +1,152 @@
+use std::f32::consts::{PI, TAU};
+use std::sync::RwLock;
+
+use bevy::prelude::*;
+
+use crate::flock::{BoidsRules, LocalRules, SteeringPressure};
+use crate::physics::{Acceleration, Velocity};
+
+/// Both steering accumulators, behaviours write to the context when the boid has one
+pub type SteeringOutput = (&'static SteeringPressure, Option<&'static ContextSteering>);
+
+/// Context steering accumulator, an alternative to `SteeringPressure`.
+///
+/// Behaviours write how much they want to go (interest) or not go (danger) in each of a fixed
+/// set of directions, instead of summing forces. Opposing wishes then no longer cancel out,
+/// the resolver picks the most interesting direction among the safest ones.
+///
+/// Boids with this component have their behaviours write here, their `SteeringPressure` is
+/// left untouched.
+#[derive(Component)]
+pub struct ContextSteering {
+    pub directions: Vec<Vec3>,
+    pub interest: RwLock<Vec<f32>>,
+    pub danger: RwLock<Vec<f32>>,
+    /// Slots with more danger than the safest slot plus this are masked out
+    pub danger_tolerance: f32,
+    /// Cosine of the angle between neighbouring slots, used to smooth the resolved heading
+    spread: f32,
+}
+
+impl ContextSteering {
+    /// Slots spread evenly over a sphere, for boids moving in 3D
+    pub fn sphere(resolution: usize) -> Self {
+        let resolution = resolution.max(1);
+        let golden = PI * (3.0 - 5f32.sqrt());
+
+        // Fibonacci lattice
+        let directions = (0..resolution)
+            .map(|i| {
+                let y = 1.0 - 2.0 * (i as f32 + 0.5) / resolution as f32;
+                let radius = (1.0 - y * y).sqrt();
+                let theta = golden * i as f32;
+                Vec3::new(theta.cos() * radius, y, theta.sin() * radius)
+            })
+            .collect();
+
+        let spacing = (4.0 * PI / resolution as f32).sqrt();
+        Self::from_directions(directions, spacing)
+    }
+
+    /// Slots spread evenly over a ring in the XY plane, for boids moving in 2D
+    pub fn ring(resolution: usize) -> Self {
+        let resolution = resolution.max(1);
+        let spacing = TAU / resolution as f32;
+
+        let directions = (0..resolution)
+            .map(|i| {
+                let angle = spacing * i as f32;
+                Vec3::new(angle.cos(), angle.sin(), 0.0)
+            })
+            .collect();
+
+        Self::from_directions(directions, spacing)
+    }
+
+    fn from_directions(directions: Vec<Vec3>, spacing: f32) -> Self {
+        let slots = directions.len();
+        Self {
+            directions,
+            interest: RwLock::new(vec![0.0; slots]),
+            danger: RwLock::new(vec![0.0; slots]),
+            danger_tolerance: 0.1,
+            spread: spacing.min(PI).cos(),
+        }
+    }
+
+    /// Adds interest toward `direction`, falling off with the angle to each slot
+    pub fn add_interest(&self, direction: Vec3, weight: f32) {
+        write_slots(&self.directions, &self.interest, direction, weight);
+    }
+
+    /// Adds danger toward `direction`, falling off with the angle to each slot.
+    /// A weight of 1 stops the boid from moving that way at all, larger weights are clamped to it
+    /// so a behaviour with a large factor cannot mask the danger from every other one.
+    pub fn add_danger(&self, direction: Vec3, weight: f32) {
+        write_slots(&self.directions, &self.danger, direction, weight.min(1.0));
+    }
+
+    /// Heading and speed fraction picked from the current maps, None when nothing is of interest
+    pub fn resolve(&self) -> Option<(Vec3, f32)> {
+        let interest = self.interest.read().unwrap();
+        let danger = self.danger.read().unwrap();
+
+        let safest = danger.iter().copied().fold(f32::INFINITY, f32::min);
+        let open = |i: &usize| danger[*i] <= safest + self.danger_tolerance;
+
+        let best = (0..self.directions.len())
+            .filter(open)
+            .max_by(|a, b| interest[*a].total_cmp(&interest[*b]))?;
+        if interest[best] <= 0.0 {
+            return None;
+        }
+
+        // Blend in the open neighbouring slots so the heading is not stuck to the slots
+        let heading: Vec3 = (0..self.directions.len())
+            .filter(open)
+            .filter(|i| self.directions[*i].dot(self.directions[best]) >= self.spread - 1e-4)
+            .map(|i| self.directions[i] * interest[i].max(0.0))
+            .sum();
+
+        let speed = 1.0 - danger[best].clamp(0.0, 1.0);
+        Some((heading.normalize_or_zero(), speed))
+    }
+
+    pub fn clear(&self) {
+        self.interest.write().unwrap().fill(0.0);
+        self.danger.write().unwrap().fill(0.0);
+    }
+}
+
+/// Each slot keeps the strongest wish pointing its way rather than a sum
+fn write_slots(directions: &[Vec3], slots: &RwLock<Vec<f32>>, direction: Vec3, weight: f32) {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO || weight <= 0.0 {
+        return;
+    }
+
+    let mut slots = slots.write().unwrap();
+    for (slot, dir) in slots.iter_mut().zip(directions) {
+        let value = dir.dot(direction).max(0.0) * weight;
+        *slot = slot.max(value);
+    }
+}
+
+/// Turns the resolved heading into a steering force toward the desired speed
+pub fn context_steering_system(
+    mut query: Query<(
+        &mut Acceleration,
+        &Velocity,
+        &ContextSteering,
+        Option<&LocalRules>,
+    )>,
+    rules: Res<BoidsRules>,
+) {
+    for (mut acc, vel, context, local) in &mut query {
+        if let Some((heading, speed)) = context.resolve() {
+            let desired = heading * rules.local(local).desired_speed * speed;
+            acc.vec += desired - vel.vec;
+        }
+        context.clear();
+    }
+}
@@ -0,0 +1,76 @@
+use crate::flock::{BoidsRules, LocalRules};
+use crate::physics::{Acceleration, Velocity};
+use bevy::prelude::*;
+
+/// Energy reserve of a boid.
+///
+/// Steering and speed drain the reserve, gliding lets it recover. An exhausted boid can only
+/// use a fraction of `BoidsRules::max_force` and `BoidsRules::max_velocity`.
+#[derive(Component, Clone, Copy)]
+pub struct Energy {
+    pub value: f32,
+    pub max: f32,
+    /// Energy spent per second for each unit of applied acceleration
+    pub effort_cost: f32,
+    /// Energy spent per second for each unit of speed
+    pub speed_cost: f32,
+    /// Energy regained per second while gliding
+    pub recovery: f32,
+    /// Below this acceleration the boid is gliding
+    pub glide_threshold: f32,
+    /// Fraction of the force and velocity limits left once the reserve is empty
+    pub exhausted_scale: f32,
+}
+
+impl Default for Energy {
+    fn default() -> Self {
+        Self {
+            value: 100.0,
+            max: 100.0,
+            effort_cost: 0.01,
+            speed_cost: 0.02,
+            recovery: 5.0,
+            glide_threshold: 10.0,
+            exhausted_scale: 0.3,
+        }
+    }
+}
+
+impl Energy {
+    pub fn ratio(&self) -> f32 {
+        if self.max <= 0.0 {
+            0.0
+        } else {
+            (self.value / self.max).clamp(0.0, 1.0)
+        }
+    }
+
+    /// Multiplier applied to the force and velocity limits
+    pub fn limit_scale(&self) -> f32 {
+        self.exhausted_scale + (1.0 - self.exhausted_scale) * self.ratio()
+    }
+}
+
+/// Drains energy from the acceleration about to be applied, must run before the force application
+pub fn energy_system(
+    mut query: Query<(&mut Energy, &Acceleration, &Velocity, Option<&LocalRules>)>,
+    rules: Res<BoidsRules>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+
+    for (mut energy, acc, vel, local) in &mut query {
+        // The force application clamps the acceleration, only pay for what is actually used
+        let effort = acc
+            .vec
+            .length()
+            .min(rules.local(local).max_force * energy.limit_scale());
+        let mut change = -(effort * energy.effort_cost + vel.vec.length() * energy.speed_cost);
+
+        if effort < energy.glide_threshold {
+            change += energy.recovery;
+        }
+
+        energy.value = (energy.value + change * delta).clamp(0.0, energy.max);
+    }
+}
@@ -0,0 +1,411 @@
+use crate::behaviours::state::BehaviourState;
+use crate::behaviours::BehaviourFactors;
+use crate::energy::Energy;
+use crate::flock::{random_direction, GameArea};
+use crate::lifecycle::Mutation;
+use crate::perception::Perception;
+use crate::physics::Velocity;
+use crate::predator::{nearest_threat, Predator};
+use crate::BoidStage;
+use bevy::ecs::query::{QueryItem, ROQueryItem};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The behaviour factors of a boid, seen as a set of genes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Genome {
+    pub coherence: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub world_bound: f32,
+    pub avoidance: f32,
+    pub desired_velocity: f32,
+}
+
+impl Genome {
+    pub fn genes(&self) -> [f32; 6] {
+        [
+            self.coherence,
+            self.separation,
+            self.alignment,
+            self.world_bound,
+            self.avoidance,
+            self.desired_velocity,
+        ]
+    }
+
+    pub fn from_genes(genes: [f32; 6]) -> Self {
+        Self {
+            coherence: genes[0],
+            separation: genes[1],
+            alignment: genes[2],
+            world_bound: genes[3],
+            avoidance: genes[4],
+            desired_velocity: genes[5],
+        }
+    }
+
+    /// Uniform crossover followed by mutation of every gene
+    pub fn breed(&self, other: &Genome, mutation: &Mutation, rng: &mut impl Rng) -> Genome {
+        let (a, b) = (self.genes(), other.genes());
+        let mut genes = [0.0; 6];
+        for i in 0..genes.len() {
+            genes[i] = mutation.inherit(a[i], b[i], rng);
+        }
+        Genome::from_genes(genes)
+    }
+}
+
+/// What a fitness function can observe about a boid each frame
+pub struct FitnessSample {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub neighbours: usize,
+    /// Fraction of energy left, if the boid has an energy reserve
+    pub energy: Option<f32>,
+    /// Distance to the nearest predator, if any
+    pub threat: Option<f32>,
+}
+
+pub trait FitnessFunction: Send + Sync + 'static {
+    /// Score earned over `delta` seconds, or None if the boid is out for the rest of the generation
+    fn evaluate(&self, sample: &FitnessSample, delta: f32) -> Option<f32>;
+}
+
+/// Rewards staying alive, a boid is caught when a predator comes within the catch radius
+pub struct Survival {
+    pub catch_radius: f32,
+}
+
+impl FitnessFunction for Survival {
+    fn evaluate(&self, sample: &FitnessSample, delta: f32) -> Option<f32> {
+        match sample.threat {
+            Some(distance) if distance <= self.catch_radius => None,
+            _ => Some(delta),
+        }
+    }
+}
+
+/// Rewards keeping the energy reserve high
+pub struct EnergyFitness;
+
+impl FitnessFunction for EnergyFitness {
+    fn evaluate(&self, sample: &FitnessSample, delta: f32) -> Option<f32> {
+        Some(sample.energy.unwrap_or(1.0) * delta)
+    }
+}
+
+/// Rewards staying with the flock, up to the ideal number of neighbours
+pub struct Cohesion {
+    pub ideal_neighbours: usize,
+}
+
+impl FitnessFunction for Cohesion {
+    fn evaluate(&self, sample: &FitnessSample, delta: f32) -> Option<f32> {
+        let ideal = self.ideal_neighbours.max(1);
+        Some(sample.neighbours.min(ideal) as f32 / ideal as f32 * delta)
+    }
+}
+
+/// Weighted sum of several fitness functions, eliminated as soon as one of them eliminates
+pub struct WeightedFitness(pub Vec<(f32, Box<dyn FitnessFunction>)>);
+
+impl FitnessFunction for WeightedFitness {
+    fn evaluate(&self, sample: &FitnessSample, delta: f32) -> Option<f32> {
+        self.0
+            .iter()
+            .map(|(weight, f)| f.evaluate(sample, delta).map(|s| s * weight))
+            .sum()
+    }
+}
+
+#[derive(Component)]
+pub struct Fitness {
+    pub score: f32,
+    pub alive: bool,
+}
+
+impl Default for Fitness {
+    fn default() -> Self {
+        Self {
+            score: 0.0,
+            alive: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub best: f32,
+    pub mean: f32,
+    pub worst: f32,
+    pub survivors: usize,
+    pub best_genome: Genome,
+}
+
+#[derive(Resource)]
+pub struct Evolution {
+    pub generation: u32,
+    /// Seconds simulated for each generation
+    pub generation_time: f32,
+    pub elapsed: f32,
+    /// Simulated seconds per frame, None follows the wall clock.
+    ///
+    /// With a fixed step `Time` advances by the same amount every frame, so a generation covers
+    /// the same simulation however fast the app runs. Headless under `MinimalPlugins` the app
+    /// loops without waiting and generations go by as fast as the machine allows.
+    pub step: Option<f32>,
+    /// Number of best genomes copied unchanged into the next generation
+    pub elite: usize,
+    /// Number of genomes competing for each parent slot
+    pub tournament: usize,
+    pub mutation: Mutation,
+    pub fitness: Box<dyn FitnessFunction>,
+    pub history: Vec<GenerationStats>,
+    /// CSV file each generation is appended to
+    pub log: Option<PathBuf>,
+}
+
+impl Default for Evolution {
+    fn default() -> Self {
+        Self {
+            generation: 0,
+            generation_time: 60.0,
+            elapsed: 0.0,
+            step: Some(1.0 / 60.0),
+            elite: 2,
+            tournament: 3,
+            mutation: Mutation {
+                rate: 0.2,
+                strength: 0.2,
+            },
+            fitness: Box::new(Survival { catch_radius: 1.0 }),
+            history: Vec::new(),
+            log: None,
+        }
+    }
+}
+
+pub struct EvolutionPlugin;
+
+impl Plugin for EvolutionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Evolution>()
+            .add_systems((time_step_system, genome_conflict_system))
+            .add_systems(
+                (fitness_system, generation_system)
+                    .chain()
+                    .after(BoidStage::ForceApplication),
+            );
+    }
+}
+
+/// Switches `Time` between the fixed evolution step and the wall clock
+pub fn time_step_system(mut commands: Commands, evolution: Res<Evolution>) {
+    if !evolution.is_changed() {
+        return;
+    }
+
+    commands.insert_resource(match evolution.step {
+        Some(step) => TimeUpdateStrategy::ManualDuration(Duration::from_secs_f32(step)),
+        None => TimeUpdateStrategy::Automatic,
+    });
+}
+
+type GenomeConflict = (
+    With<Fitness>,
+    With<BehaviourState>,
+    Or<(Added<Fitness>, Added<BehaviourState>)>,
+);
+
+/// Warns about boids whose genome would be overwritten by a `BehaviourState` every frame
+pub fn genome_conflict_system(query: Query<Entity, GenomeConflict>) {
+    for entity in &query {
+        warn!(
+            "{:?} has both a Fitness and a BehaviourState, its state profiles overwrite the evolved factors",
+            entity
+        );
+    }
+}
+
+pub fn fitness_system(
+    mut query: Query<(
+        &mut Fitness,
+        &Transform,
+        &Velocity,
+        &Perception,
+        Option<&Energy>,
+    )>,
+    predators: Query<(Entity, &Transform, Option<&Velocity>), With<Predator>>,
+    evolution: Res<Evolution>,
+    time: Res<Time>,
+) {
+    for (mut fitness, tf, vel, per, energy) in &mut query {
+        if !fitness.alive {
+            continue;
+        }
+
+        let sample = FitnessSample {
+            position: tf.translation,
+            velocity: vel.vec,
+            neighbours: per.list.len().saturating_sub(1),
+            energy: energy.map(|e| e.ratio()),
+            threat: nearest_threat(tf.translation, f32::INFINITY, &predators).map(|t| t.distance),
+        };
+
+        match evolution.fitness.evaluate(&sample, time.delta_seconds()) {
+            Some(score) => fitness.score += score,
+            None => fitness.alive = false,
+        }
+    }
+}
+
+pub fn generation_system(
+    mut query: Query<(
+        &mut Fitness,
+        &mut Transform,
+        &mut Velocity,
+        BehaviourFactors,
+    )>,
+    mut evolution: ResMut<Evolution>,
+    area: Option<Res<GameArea>>,
+    time: Res<Time>,
+) {
+    evolution.elapsed += time.delta_seconds();
+    if evolution.elapsed < evolution.generation_time {
+        return;
+    }
+
+    let mut ranked: Vec<(Genome, f32, bool)> = query
+        .iter()
+        .map(|(fitness, _, _, genes)| (read_genome(&genes), fitness.score, fitness.alive))
+        .collect();
+    if ranked.is_empty() {
+        return;
+    }
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    let stats = GenerationStats {
+        generation: evolution.generation,
+        best: ranked[0].1,
+        mean: ranked.iter().map(|r| r.1).sum::<f32>() / ranked.len() as f32,
+        worst: ranked[ranked.len() - 1].1,
+        survivors: ranked.iter().filter(|r| r.2).count(),
+        best_genome: ranked[0].0,
+    };
+    log_generation(&evolution, &stats);
+
+    // Keep the elite and fill the rest of the population through tournaments
+    let mut rng = rand::thread_rng();
+    let mut next: Vec<Genome> = ranked.iter().take(evolution.elite).map(|r| r.0).collect();
+    while next.len() < ranked.len() {
+        let a = tournament(&ranked, evolution.tournament, &mut rng);
+        let b = tournament(&ranked, evolution.tournament, &mut rng);
+        next.push(a.breed(&b, &evolution.mutation, &mut rng));
+    }
+    next.shuffle(&mut rng);
+
+    for ((mut fitness, mut tf, mut vel, mut genes), genome) in query.iter_mut().zip(next) {
+        write_genome(&mut genes, &genome);
+        *fitness = Fitness::default();
+        vel.vec = random_direction();
+        if let Some(area) = &area {
+            tf.translation = area.random_transform().translation;
+        }
+    }
+
+    evolution.history.push(stats);
+    evolution.generation += 1;
+    evolution.elapsed = 0.0;
+}
+
+fn tournament(ranked: &[(Genome, f32, bool)], size: usize, rng: &mut impl Rng) -> Genome {
+    (0..size.max(1))
+        .map(|_| &ranked[rng.gen_range(0..ranked.len())])
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|r| r.0)
+        .unwrap()
+}
+
+fn read_genome(genes: &ROQueryItem<BehaviourFactors>) -> Genome {
+    let (coh, sep, ali, bound, avoid, des) = genes;
+    Genome {
+        coherence: coh.map_or(0.0, |c| c.factor),
+        separation: sep.map_or(0.0, |c| c.factor),
+        alignment: ali.map_or(0.0, |c| c.factor),
+        world_bound: bound.map_or(0.0, |c| c.factor),
+        avoidance: avoid.map_or(0.0, |c| c.factor),
+        desired_velocity: des.map_or(0.0, |c| c.factor),
+    }
+}
+
+fn write_genome(genes: &mut QueryItem<BehaviourFactors>, genome: &Genome) {
+    let (coh, sep, ali, bound, avoid, des) = genes;
+    if let Some(c) = coh {
+        c.factor = genome.coherence;
+    }
+    if let Some(c) = sep {
+        c.factor = genome.separation;
+    }
+    if let Some(c) = ali {
+        c.factor = genome.alignment;
+    }
+    if let Some(c) = bound {
+        c.factor = genome.world_bound;
+    }
+    if let Some(c) = avoid {
+        c.factor = genome.avoidance;
+    }
+    if let Some(c) = des {
+        c.factor = genome.desired_velocity;
+    }
+}
+
+fn log_generation(evolution: &Evolution, stats: &GenerationStats) {
+    info!(
+        "generation {}: best {:.2}, mean {:.2}, worst {:.2}, survivors {}, best genome {:?}",
+        stats.generation, stats.best, stats.mean, stats.worst, stats.survivors, stats.best_genome
+    );
+
+    let Some(path) = &evolution.log else {
+        return;
+    };
+
+    let write_header = !path.exists();
+    let file = OpenOptions::new().create(true).append(true).open(path);
+    let result = file.and_then(|mut file| {
+        if write_header {
+            writeln!(
+                file,
+                "generation,best,mean,worst,survivors,coherence,separation,alignment,world_bound,avoidance,desired_velocity"
+            )?;
+        }
+        let g = stats.best_genome.genes();
+        writeln!(
+            file,
+            "{},{},{},{},{},{},{},{},{},{},{}",
+            stats.generation,
+            stats.best,
+            stats.mean,
+            stats.worst,
+            stats.survivors,
+            g[0],
+            g[1],
+            g[2],
+            g[3],
+            g[4],
+            g[5]
+        )
+    });
+
+    if let Err(err) = result {
+        warn!("could not write evolution log {:?}: {}", path, err);
+    }
+}
@@ -1,40 +1,360 @@
 use bevy::math::vec3;
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
 use rand::Rng;
 use std::sync::RwLock;
 
+use crate::energy::Energy;
 use crate::physics::Acceleration;
 
-#[derive(Resource, Default)]
+#[derive(Resource, Default, Clone, Copy, Debug)]
 pub struct BoidsRules {
     pub desired_speed: f32,
     pub max_force: f32,
     pub max_velocity: f32,
 }
 
+impl BoidsRules {
+    /// The rules a boid follows, its own when it has some
+    pub fn local(&self, local: Option<&LocalRules>) -> BoidsRules {
+        local.map_or(*self, |l| l.0)
+    }
+}
+
+/// Rules of a single boid, used instead of the global `BoidsRules`
+#[derive(Component, Clone, Copy, Debug)]
+pub struct LocalRules(pub BoidsRules);
+
+/// Shape of the world boids are kept in
+#[derive(Clone, Debug)]
+pub enum Bounds {
+    Box(shape::Box),
+    Sphere {
+        radius: f32,
+    },
+    /// Upright along Y
+    Cylinder {
+        radius: f32,
+        half_height: f32,
+    },
+    /// Upright along Y, `half_height` excludes the caps
+    Capsule {
+        radius: f32,
+        half_height: f32,
+    },
+    /// Inside any of the boxes
+    Union(Vec<shape::Box>),
+    /// Inside of the rapier collider on this entity, placed by its own transform
+    Collider(Entity),
+}
+
+impl From<shape::Box> for Bounds {
+    fn from(area: shape::Box) -> Self {
+        Bounds::Box(area)
+    }
+}
+
+impl Bounds {
+    /// Signed distance to the surface, negative inside. None for collider bounds
+    pub fn signed_distance(&self, point: Vec3) -> Option<f32> {
+        let distance = match self {
+            Bounds::Box(area) => box_distance(area, point),
+            Bounds::Sphere { radius } => point.length() - radius,
+            Bounds::Cylinder {
+                radius,
+                half_height,
+            } => {
+                let d = Vec2::new(
+                    Vec2::new(point.x, point.z).length() - radius,
+                    point.y.abs() - half_height,
+                );
+                d.max_element().min(0.0) + d.max(Vec2::ZERO).length()
+            }
+            Bounds::Capsule {
+                radius,
+                half_height,
+            } => {
+                let axis = Vec3::Y * point.y.clamp(-half_height, *half_height);
+                (point - axis).length() - radius
+            }
+            Bounds::Union(boxes) => boxes
+                .iter()
+                .map(|area| box_distance(area, point))
+                .reduce(f32::min)?,
+            Bounds::Collider(_) => return None,
+        };
+        Some(distance)
+    }
+
+    /// Box around the bounds. None for collider bounds
+    pub fn aabb(&self) -> Option<shape::Box> {
+        let aabb = |half: Vec3| shape::Box::new(half.x * 2.0, half.y * 2.0, half.z * 2.0);
+        match self {
+            Bounds::Box(area) => Some(*area),
+            Bounds::Sphere { radius } => Some(aabb(Vec3::splat(*radius))),
+            Bounds::Cylinder {
+                radius,
+                half_height,
+            } => Some(aabb(vec3(*radius, *half_height, *radius))),
+            Bounds::Capsule {
+                radius,
+                half_height,
+            } => Some(aabb(vec3(*radius, half_height + radius, *radius))),
+            Bounds::Union(boxes) => boxes.iter().copied().reduce(|a, b| shape::Box {
+                min_x: a.min_x.min(b.min_x),
+                max_x: a.max_x.max(b.max_x),
+                min_y: a.min_y.min(b.min_y),
+                max_y: a.max_y.max(b.max_y),
+                min_z: a.min_z.min(b.min_z),
+                max_z: a.max_z.max(b.max_z),
+            }),
+            Bounds::Collider(_) => None,
+        }
+    }
+}
+
+fn box_distance(area: &shape::Box, point: Vec3) -> f32 {
+    let min = vec3(area.min_x, area.min_y, area.min_z);
+    let max = vec3(area.max_x, area.max_y, area.max_z);
+    let q = (point - (min + max) * 0.5).abs() - (max - min) * 0.5;
+    q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+}
+
+/// What happens to a boid at the edge of the area, set on `GameArea` or per boid
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub enum BoundaryMode {
+    /// Pulled back in proportionally to how far out it is
+    #[default]
+    Spring,
+    /// Steered back in from `margin` before the edge
+    Soft { margin: f32 },
+    /// Bounces off the edge
+    Reflect,
+    /// Stopped at the edge, keeping the velocity along it
+    Clamp,
+    /// Comes back in from the opposite side
+    Wrap,
+    /// Despawned, and replaced through `BoundarySpawner` at a random point in the area
+    Respawn,
+}
+
 #[derive(Resource)]
 pub struct GameArea {
+    /// Moves the bounds, collider bounds use their collider's transform instead
     pub offset: Vec3,
-    pub area: shape::Box,
+    pub area: Bounds,
+    pub mode: BoundaryMode,
+}
+
+impl GameArea {
+    /// Signed distance to the edge of the area, negative inside, and the outward normal there
+    pub fn boundary(&self, point: Vec3, rapier: Option<&RapierContext>) -> Option<(f32, Vec3)> {
+        if let Bounds::Collider(entity) = self.area {
+            let predicate = |e| e == entity;
+            let filter = QueryFilter::new().predicate(&predicate);
+            let (_, projection) = rapier?.project_point(point, false, filter)?;
+            let (distance, outward) = if projection.is_inside {
+                (-point.distance(projection.point), projection.point - point)
+            } else {
+                (point.distance(projection.point), point - projection.point)
+            };
+            return Some((distance, outward.normalize_or_zero()));
+        }
+
+        let local = point - self.offset;
+        let distance = self.area.signed_distance(local)?;
+
+        // Central differences of the distance field
+        let h = 0.01;
+        let d = |offset: Vec3| {
+            self.area
+                .signed_distance(local + offset)
+                .unwrap_or(distance)
+        };
+        let gradient = vec3(
+            d(Vec3::X * h) - d(-Vec3::X * h),
+            d(Vec3::Y * h) - d(-Vec3::Y * h),
+            d(Vec3::Z * h) - d(-Vec3::Z * h),
+        );
+        Some((distance, gradient.normalize_or_zero()))
+    }
+
+    pub fn contains(&self, point: Vec3, rapier: Option<&RapierContext>) -> bool {
+        matches!(self.boundary(point, rapier), Some((d, _)) if d <= 0.0)
+    }
+
+    /// Point on the opposite side of the area, moved inside when it falls outside the bounds
+    pub fn wrap(&self, point: Vec3, rapier: Option<&RapierContext>) -> Vec3 {
+        let local = point - self.offset;
+        let wrapped = match (&self.area, self.area.aabb()) {
+            (Bounds::Box(_) | Bounds::Union(_), Some(aabb)) => {
+                let min = vec3(aabb.min_x, aabb.min_y, aabb.min_z);
+                let size =
+                    (vec3(aabb.max_x, aabb.max_y, aabb.max_z) - min).max(Vec3::splat(f32::EPSILON));
+                let from_min = local - min;
+                self.offset + min + from_min - size * (from_min / size).floor()
+            }
+            // Round shapes have no opposite faces, go through the centre instead
+            _ => self.offset - local,
+        };
+
+        match self.boundary(wrapped, rapier) {
+            Some((distance, normal)) if distance > 0.0 => wrapped - normal * distance,
+            _ => wrapped,
+        }
+    }
+
+    /// Random transform inside the area. Collider bounds need `random_point`, they are only
+    /// known to rapier, and start at the offset here
+    pub fn random_transform(&self) -> Transform {
+        Transform::from_translation(self.random_point(None).unwrap_or(self.offset))
+    }
+
+    /// Random point inside the area, None when no sample landed inside
+    pub fn random_point(&self, rapier: Option<&RapierContext>) -> Option<Vec3> {
+        let (aabb, offset) = match self.area {
+            Bounds::Collider(entity) => {
+                let rapier = rapier?;
+                let handle = rapier.entity2collider().get(&entity)?;
+                let aabb = rapier.colliders.get(*handle)?.compute_aabb();
+                let area = shape::Box {
+                    min_x: aabb.mins.x,
+                    max_x: aabb.maxs.x,
+                    min_y: aabb.mins.y,
+                    max_y: aabb.maxs.y,
+                    min_z: aabb.mins.z,
+                    max_z: aabb.maxs.z,
+                };
+                // The collider's box is already in world space
+                (area, Vec3::ZERO)
+            }
+            _ => (self.area.aabb()?, self.offset),
+        };
+
+        (0..32)
+            .map(|_| random_transform(aabb).translation + offset)
+            .find(|&point| self.contains(point, rapier))
+    }
+}
+
+/// Tags boids belonging to the same flock, for effects that only target one flock
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FlockId(pub u32);
+
+/// How the steering forces of the different behaviours are combined
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SteeringArbitration {
+    /// Every force is summed, the total is clamped to the max force
+    #[default]
+    Sum,
+    /// The max force is handed out by priority, lower priorities get what is left
+    Prioritised,
+}
+
+/// Priority of each built-in behaviour, lower values are served first
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SteeringPriorities {
+    pub avoidance: u8,
+    pub bounds: u8,
+    /// Shares the bounds priority by default
+    pub altitude: u8,
+    pub evasion: u8,
+    pub separation: u8,
+    /// Shares the separation priority by default
+    pub unaligned: u8,
+    pub roosting: u8,
+    pub foraging: u8,
+    pub zones: u8,
+    pub alignment: u8,
+    pub coherence: u8,
+    pub desired_velocity: u8,
+}
+
+impl Default for SteeringPriorities {
+    fn default() -> Self {
+        Self {
+            avoidance: 0,
+            bounds: 1,
+            altitude: 1,
+            evasion: 2,
+            separation: 3,
+            unaligned: 3,
+            roosting: 4,
+            foraging: 5,
+            zones: 6,
+            alignment: 7,
+            coherence: 8,
+            desired_velocity: 9,
+        }
+    }
 }
 
 #[derive(Component, Default)]
 pub struct SteeringPressure {
+    /// Forces without a priority, served last when arbitrating
     pub lock: RwLock<Vec3>,
+    /// Forces summed per priority
+    pub layers: RwLock<Vec<(u8, Vec3)>>,
 }
 
-pub fn boid_integrator_system(mut query: Query<(&mut Acceleration, &SteeringPressure)>) {
-    for (mut acc, steer) in &mut query {
-        let force = steer.lock.read().unwrap();
-        acc.vec += *force;
-        drop(force);
+impl SteeringPressure {
+    pub fn add(&self, priority: u8, force: Vec3) {
+        let mut layers = self.layers.write().unwrap();
+        match layers.iter_mut().find(|(p, _)| *p == priority) {
+            Some((_, sum)) => *sum += force,
+            None => layers.push((priority, force)),
+        }
+    }
+}
 
+pub fn boid_integrator_system(
+    mut query: Query<(
+        &mut Acceleration,
+        &SteeringPressure,
+        Option<&Energy>,
+        Option<&LocalRules>,
+    )>,
+    mode: Res<SteeringArbitration>,
+    rules: Res<BoidsRules>,
+) {
+    for (mut acc, steer, energy, local) in &mut query {
         let mut force = steer.lock.write().unwrap();
+        let mut layers = steer.layers.write().unwrap();
+        layers.push((u8::MAX, *force));
+
+        acc.vec += match *mode {
+            SteeringArbitration::Sum => layers.iter().map(|(_, f)| *f).sum(),
+            SteeringArbitration::Prioritised => {
+                let budget = rules.local(local).max_force * energy.map_or(1.0, |e| e.limit_scale());
+                allocate_by_priority(&mut layers, budget)
+            }
+        };
+
+        layers.clear();
         *force = Vec3::ZERO;
-        drop(force);
     }
 }
 
+/// Prioritised acceleration allocation: each layer takes what it needs from the budget in
+/// priority order, and the first layer that does not fit is truncated to what is left.
+fn allocate_by_priority(layers: &mut [(u8, Vec3)], budget: f32) -> Vec3 {
+    layers.sort_by_key(|(priority, _)| *priority);
+
+    let mut remaining = budget;
+    let mut total = Vec3::ZERO;
+    for (_, force) in layers.iter() {
+        let magnitude = force.length();
+        if magnitude <= remaining {
+            total += *force;
+            remaining -= magnitude;
+        } else {
+            total += force.normalize_or_zero() * remaining;
+            break;
+        }
+    }
+    total
+}
+
 pub fn random_transform(area: shape::Box) -> Transform {
     let mut rng = rand::thread_rng();
 
@@ -57,3 +377,114 @@ pub fn random_direction() -> Vec3 {
     let pos = vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
     pos
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_distance(bounds: &Bounds, point: Vec3, expected: f32) {
+        let distance = bounds.signed_distance(point).unwrap();
+        assert!(
+            (distance - expected).abs() < 1e-5,
+            "{:?}: {} instead of {}",
+            point,
+            distance,
+            expected
+        );
+    }
+
+    #[test]
+    fn box_distance_is_negative_inside() {
+        let bounds = Bounds::Box(shape::Box::new(4.0, 2.0, 2.0));
+        assert_distance(&bounds, Vec3::ZERO, -1.0);
+        assert_distance(&bounds, vec3(1.5, 0.0, 0.0), -0.5);
+        assert_distance(&bounds, vec3(2.0, 0.0, 0.0), 0.0);
+        assert_distance(&bounds, vec3(5.0, 0.0, 0.0), 3.0);
+        // Past a corner the distance is to the corner itself
+        assert_distance(&bounds, vec3(5.0, 5.0, 1.0), 5.0);
+    }
+
+    #[test]
+    fn sphere_distance_is_negative_inside() {
+        let bounds = Bounds::Sphere { radius: 2.0 };
+        assert_distance(&bounds, Vec3::ZERO, -2.0);
+        assert_distance(&bounds, vec3(0.0, 1.0, 0.0), -1.0);
+        assert_distance(&bounds, vec3(0.0, 0.0, 2.0), 0.0);
+        assert_distance(&bounds, vec3(3.0, 4.0, 0.0), 3.0);
+    }
+
+    #[test]
+    fn capsule_distance_is_negative_inside() {
+        let bounds = Bounds::Capsule {
+            radius: 1.0,
+            half_height: 2.0,
+        };
+        assert_distance(&bounds, Vec3::ZERO, -1.0);
+        // Inside the caps, beyond the straight part
+        assert_distance(&bounds, vec3(0.0, 2.5, 0.0), -0.5);
+        assert_distance(&bounds, vec3(0.0, -3.0, 0.0), 0.0);
+        assert_distance(&bounds, vec3(3.0, 1.0, 0.0), 2.0);
+        assert_distance(&bounds, vec3(0.0, 5.0, 0.0), 2.0);
+    }
+
+    #[test]
+    fn cylinder_distance_is_negative_inside() {
+        let bounds = Bounds::Cylinder {
+            radius: 1.0,
+            half_height: 2.0,
+        };
+        assert_distance(&bounds, Vec3::ZERO, -1.0);
+        assert_distance(&bounds, vec3(0.0, 1.5, 0.0), -0.5);
+        assert_distance(&bounds, vec3(0.0, 3.0, 0.0), 1.0);
+        assert_distance(&bounds, vec3(4.0, 6.0, 0.0), 5.0);
+    }
+
+    #[test]
+    fn union_is_inside_any_box() {
+        let bounds = Bounds::Union(vec![
+            shape::Box::new(2.0, 2.0, 2.0),
+            shape::Box {
+                min_x: 4.0,
+                max_x: 6.0,
+                min_y: -1.0,
+                max_y: 1.0,
+                min_z: -1.0,
+                max_z: 1.0,
+            },
+        ]);
+        assert_distance(&bounds, Vec3::ZERO, -1.0);
+        assert_distance(&bounds, vec3(5.0, 0.0, 0.0), -1.0);
+        assert_distance(&bounds, vec3(2.5, 0.0, 0.0), 1.5);
+        assert_eq!(
+            Bounds::Collider(Entity::PLACEHOLDER).signed_distance(Vec3::ZERO),
+            None
+        );
+    }
+
+    #[test]
+    fn allocation_keeps_every_layer_within_budget() {
+        let mut layers = [(2, Vec3::X), (1, Vec3::Y * 2.0)];
+        let total = allocate_by_priority(&mut layers, 10.0);
+        assert_eq!(total, vec3(1.0, 2.0, 0.0));
+    }
+
+    #[test]
+    fn allocation_serves_layers_in_priority_order() {
+        let mut layers = [(5, Vec3::X * 4.0), (0, Vec3::Y * 3.0)];
+        let total = allocate_by_priority(&mut layers, 5.0);
+        assert_eq!(total, vec3(2.0, 3.0, 0.0));
+    }
+
+    #[test]
+    fn allocation_drops_layers_after_the_budget_runs_out() {
+        let mut layers = [(0, Vec3::X * 6.0), (1, Vec3::Y), (2, Vec3::Z)];
+        let total = allocate_by_priority(&mut layers, 5.0);
+        assert_eq!(total, Vec3::X * 5.0);
+    }
+
+    #[test]
+    fn allocation_with_no_budget_is_zero() {
+        let mut layers = [(0, Vec3::X)];
+        assert_eq!(allocate_by_priority(&mut layers, 0.0), Vec3::ZERO);
+    }
+}
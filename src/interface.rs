@@ -1,5 +1,5 @@
-use crate::behaviours::{Alignment, Coherence, Separation};
-use bevy::prelude::{DetectChanges, Query, Res, Resource};
+use crate::behaviours::{Alignment, BehaviourState, Coherence, Separation};
+use bevy::prelude::{DetectChanges, Query, Res, Resource, Without};
 
 #[derive(Default, Resource)]
 pub struct UiState {
@@ -9,8 +9,9 @@ pub struct UiState {
     pub dirty: bool,
 }
 
+/// Boids driven by a `BehaviourState` keep their own weights
 pub fn adjust_from_ui_system(
-    mut query: Query<(&mut Coherence, &mut Separation, &mut Alignment)>,
+    mut query: Query<(&mut Coherence, &mut Separation, &mut Alignment), Without<BehaviourState>>,
     res: Res<UiState>,
 ) {
     if !res.is_changed() {
@@ -1,10 +1,16 @@
 extern crate core;
 
+use crate::behaviours::FactorScale;
 use crate::boid::Boid;
-use crate::flock::{boid_integrator_system, SteeringPressure};
+use crate::context::context_steering_system;
+use crate::energy::energy_system;
+use crate::flock::{
+    boid_integrator_system, SteeringArbitration, SteeringPressure, SteeringPriorities,
+};
 use crate::perception::{perception_system, rapier_perception_system, Perception};
 use crate::physics::{
-    force_application_system, rotation_system, velocity_system, Acceleration, Velocity,
+    boid_group_system, force_application_system, rotation_system, velocity_system, Acceleration,
+    ColliderFilters, Velocity,
 };
 use crate::spatial::partition::{spatial_hash_system, SpatialRes};
 use bevy::math::ivec3;
@@ -12,13 +18,25 @@ use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 use spatial::index_partition::IndexPartition;
 
+pub mod alarm;
 pub mod behaviours;
 pub mod boid;
+pub mod collision;
+pub mod context;
+pub mod energy;
+pub mod evolution;
 pub mod flock;
 pub mod interface;
+pub mod lifecycle;
+pub mod orca;
 pub mod perception;
 pub mod physics;
+pub mod predator;
 pub mod spatial;
+pub mod species;
+pub mod surface;
+pub mod terrain;
+pub mod volumes;
 
 pub fn velocity_angle(vel: &Vec3) -> f32 {
     f32::atan2(vel.y, vel.x)
@@ -38,11 +56,18 @@ impl Plugin for SteeringPlugin {
         app.add_system(spatial_hash_system.before(perception_system))
             .add_system(perception_system.before(BoidStage::ForceCalculation))
             .add_system(rapier_perception_system.before(BoidStage::ForceCalculation))
+            .add_system(boid_group_system)
             .add_system(rotation_system);
 
-        app.add_systems((boid_integrator_system,).in_set(BoidStage::ForceIntegration));
+        app.init_resource::<SteeringArbitration>()
+            .init_resource::<SteeringPriorities>()
+            .init_resource::<ColliderFilters>()
+            .add_systems(
+                (boid_integrator_system, context_steering_system)
+                    .in_set(BoidStage::ForceIntegration),
+            );
         app.add_systems(
-            (force_application_system, velocity_system)
+            (energy_system, force_application_system, velocity_system)
                 .chain()
                 .in_set(BoidStage::ForceApplication),
         );
@@ -52,31 +77,35 @@ impl Plugin for SteeringPlugin {
         app.configure_set(BoidStage::ForceIntegration.before(BoidStage::ForceApplication));
 
         // Rapier mandatory data
-        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-            .add_plugin(RapierDebugRenderPlugin::default())
-            .insert_resource(RapierConfiguration {
-                gravity: Vec3::ZERO,
-                physics_pipeline_active: false,
-                query_pipeline_active: true,
-                ..default()
-            })
-            .insert_resource(SpatialRes {
-                space: Box::new(IndexPartition {
-                    map: Default::default(),
-                    list_offsets: vec![
-                        ivec3(-1, 1, 0),
-                        ivec3(0, 1, 0),
-                        ivec3(1, 1, 0),
-                        ivec3(-1, 0, 0),
-                        ivec3(0, 0, 0),
-                        ivec3(1, 0, 0),
-                        ivec3(-1, -1, 0),
-                        ivec3(0, -1, 0),
-                        ivec3(1, -1, 0),
-                    ],
-                    cell_size: 64.0,
-                }),
-            });
+        app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
+
+        // The debug renderer needs a window, headless builds turn off the `debug-render` feature
+        #[cfg(feature = "debug-render")]
+        app.add_plugin(RapierDebugRenderPlugin::default());
+
+        app.insert_resource(RapierConfiguration {
+            gravity: Vec3::ZERO,
+            physics_pipeline_active: false,
+            query_pipeline_active: true,
+            ..default()
+        })
+        .insert_resource(SpatialRes {
+            space: Box::new(IndexPartition {
+                map: Default::default(),
+                list_offsets: vec![
+                    ivec3(-1, 1, 0),
+                    ivec3(0, 1, 0),
+                    ivec3(1, 1, 0),
+                    ivec3(-1, 0, 0),
+                    ivec3(0, 0, 0),
+                    ivec3(1, 0, 0),
+                    ivec3(-1, -1, 0),
+                    ivec3(0, -1, 0),
+                    ivec3(1, -1, 0),
+                ],
+                cell_size: 64.0,
+            }),
+        });
     }
 }
 
@@ -88,6 +117,7 @@ pub struct BaseFlockBundle {
     pub acc: Acceleration,
     pub mesh: SceneBundle,
     pub integrator: SteeringPressure,
+    pub scale: FactorScale,
 }
 impl Default for BaseFlockBundle {
     fn default() -> Self {
@@ -98,6 +128,7 @@ impl Default for BaseFlockBundle {
             acc: Acceleration::default(),
             mesh: Default::default(),
             integrator: SteeringPressure::default(),
+            scale: FactorScale::default(),
         }
     }
 }
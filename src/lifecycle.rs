@@ -0,0 +1,373 @@
+use crate::behaviours::avoidance::ObstacleAvoidance;
+use crate::behaviours::{Alignment, Coherence, DesiredVelocity, Separation, WorldBound};
+use crate::boid::Boid;
+use crate::energy::Energy;
+use crate::perception::Perception;
+use crate::physics::Velocity;
+use crate::spatial::partition::SpatialRes;
+use crate::species::Species;
+use crate::{BaseFlockBundle, BoidStage};
+use bevy::ecs::query::ROQueryItem;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+#[derive(Component, Default, Clone, Copy)]
+pub struct Age {
+    pub age: f32,
+    pub lifespan: f32,
+}
+
+/// How traits change from one generation to the next
+#[derive(Clone, Copy)]
+pub struct Mutation {
+    /// Probability for each trait to mutate
+    pub rate: f32,
+    /// Standard deviation of the mutation, relative to the trait value
+    pub strength: f32,
+}
+
+impl Default for Mutation {
+    fn default() -> Self {
+        Self {
+            rate: 0.1,
+            strength: 0.1,
+        }
+    }
+}
+
+impl Mutation {
+    pub fn mutate(&self, value: f32, rng: &mut impl Rng) -> f32 {
+        if !rng.gen_bool(self.rate.clamp(0.0, 1.0) as f64) {
+            return value;
+        }
+
+        match Normal::new(0.0, self.strength) {
+            Ok(normal) => (value * (1.0 + normal.sample(rng))).max(0.0),
+            Err(_) => value,
+        }
+    }
+
+    /// Picks the trait of either parent and mutates it
+    pub fn inherit(&self, a: f32, b: f32, rng: &mut impl Rng) -> f32 {
+        let value = if rng.gen_bool(0.5) { a } else { b };
+        self.mutate(value, rng)
+    }
+}
+
+#[derive(Component, Clone, Copy)]
+pub struct Reproduction {
+    /// Minimum age before the boid can mate
+    pub maturity: f32,
+    pub min_energy: f32,
+    /// Energy paid by each parent, handed to the offspring
+    pub energy_cost: f32,
+    pub cooldown: f32,
+    /// Time left before the boid can mate again
+    pub timer: f32,
+    pub mutation: Mutation,
+}
+
+impl Default for Reproduction {
+    fn default() -> Self {
+        Self {
+            maturity: 10.0,
+            min_energy: 50.0,
+            energy_cost: 25.0,
+            cooldown: 20.0,
+            timer: 0.0,
+            mutation: Mutation::default(),
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct LifecycleRules {
+    /// No offspring are spawned once the population reaches this size
+    pub max_population: usize,
+}
+
+impl Default for LifecycleRules {
+    fn default() -> Self {
+        Self {
+            max_population: 5000,
+        }
+    }
+}
+
+pub struct BirthEvent {
+    pub child: Entity,
+    pub parents: (Entity, Entity),
+}
+
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub age: f32,
+}
+
+pub struct LifecyclePlugin;
+
+impl Plugin for LifecyclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BirthEvent>()
+            .add_event::<DeathEvent>()
+            .init_resource::<LifecycleRules>()
+            .add_systems(
+                (ageing_system, reproduction_system, death_system)
+                    .chain()
+                    .after(BoidStage::ForceApplication),
+            );
+    }
+}
+
+pub fn ageing_system(mut query: Query<(&mut Age, Option<&mut Reproduction>)>, time: Res<Time>) {
+    let delta = time.delta_seconds();
+
+    for (mut age, repro) in &mut query {
+        age.age += delta;
+
+        if let Some(mut repro) = repro {
+            repro.timer = (repro.timer - delta).max(0.0);
+        }
+    }
+}
+
+type Traits = (
+    &'static Boid,
+    &'static Transform,
+    &'static Velocity,
+    &'static Perception,
+    &'static Handle<Scene>,
+    &'static Age,
+    Option<&'static Coherence>,
+    Option<&'static Separation>,
+    Option<&'static Alignment>,
+    Option<&'static WorldBound>,
+    Option<&'static ObstacleAvoidance>,
+    Option<&'static DesiredVelocity>,
+);
+
+pub fn reproduction_system(
+    mut commands: Commands,
+    mut parents: Query<(
+        Entity,
+        &Age,
+        &Perception,
+        &mut Reproduction,
+        Option<&mut Energy>,
+    )>,
+    traits: Query<Traits>,
+    population: Query<(), With<Boid>>,
+    species: Query<&Species>,
+    rules: Res<LifecycleRules>,
+    mut events: EventWriter<BirthEvent>,
+) {
+    let ready: HashSet<Entity> = parents
+        .iter()
+        .filter(|(_, age, _, repro, energy)| {
+            age.age >= repro.maturity
+                && repro.timer <= 0.0
+                && energy
+                    .as_ref()
+                    .filter(|e| e.value < repro.min_energy)
+                    .is_none()
+        })
+        .map(|(e, ..)| e)
+        .collect();
+
+    // Pair every ready boid with the first ready neighbour of its species that is still single
+    let species_of = |e: Entity| species.get(e).copied().unwrap_or_default();
+    let mut taken = HashSet::new();
+    let mut pairs = Vec::new();
+    for (entity, _, per, ..) in &parents {
+        if !ready.contains(&entity) || taken.contains(&entity) {
+            continue;
+        }
+
+        let mate = per
+            .list
+            .iter()
+            .filter(|&&e| e != entity && ready.contains(&e) && !taken.contains(&e))
+            .find(|&&e| species_of(e) == species_of(entity));
+
+        if let Some(&mate) = mate {
+            taken.insert(entity);
+            taken.insert(mate);
+            pairs.push((entity, mate));
+        }
+    }
+
+    let mut count = population.iter().count();
+    let mut rng = rand::thread_rng();
+
+    for (a, b) in pairs {
+        if count >= rules.max_population {
+            break;
+        }
+        let (Ok(ta), Ok(tb)) = (traits.get(a), traits.get(b)) else {
+            continue;
+        };
+
+        // Both parents pay for the offspring, which takes after the first one's fertility
+        let mut inherited_energy = 0.0;
+        let mut repro = None;
+        for parent in [a, b] {
+            if let Ok((_, _, _, mut r, energy)) = parents.get_mut(parent) {
+                r.timer = r.cooldown;
+                repro.get_or_insert(*r);
+                if let Some(mut energy) = energy {
+                    let paid = r.energy_cost.min(energy.value);
+                    energy.value -= paid;
+                    inherited_energy += paid;
+                }
+            }
+        }
+
+        let Some(repro) = repro else {
+            continue;
+        };
+
+        let child = spawn_offspring(
+            &mut commands,
+            ta,
+            tb,
+            Reproduction {
+                timer: repro.cooldown,
+                ..repro
+            },
+            &repro.mutation,
+            &mut rng,
+        );
+        if let Ok(species) = species.get(a) {
+            commands.entity(child).insert(*species);
+        }
+        if inherited_energy > 0.0 {
+            commands.entity(child).insert(Energy {
+                value: inherited_energy,
+                ..default()
+            });
+        }
+
+        count += 1;
+        events.send(BirthEvent {
+            child,
+            parents: (a, b),
+        });
+    }
+}
+
+fn spawn_offspring(
+    commands: &mut Commands,
+    a: ROQueryItem<Traits>,
+    b: ROQueryItem<Traits>,
+    reproduction: Reproduction,
+    mutation: &Mutation,
+    rng: &mut impl Rng,
+) -> Entity {
+    let (boid, tf_a, vel_a, per_a, scene, age_a, coh_a, sep_a, ali_a, bound_a, avoid_a, des_a) = a;
+    let (_, tf_b, vel_b, per_b, _, age_b, coh_b, sep_b, ali_b, bound_b, avoid_b, des_b) = b;
+
+    let transform = Transform::from_translation((tf_a.translation + tf_b.translation) / 2.0);
+
+    let mut child = commands.spawn(BaseFlockBundle {
+        boid: *boid,
+        perception: Perception {
+            range: mutation.inherit(per_a.range, per_b.range, rng),
+            ..default()
+        },
+        vel: Velocity {
+            vec: (vel_a.vec + vel_b.vec) / 2.0,
+        },
+        mesh: SceneBundle {
+            scene: scene.clone(),
+            transform,
+            ..default()
+        },
+        ..default()
+    });
+
+    child.insert((
+        Age {
+            age: 0.0,
+            lifespan: mutation.inherit(age_a.lifespan, age_b.lifespan, rng),
+        },
+        reproduction,
+    ));
+
+    // Behaviours are only passed on when both parents have them
+    if let (Some(a), Some(b)) = (coh_a, coh_b) {
+        child.insert(Coherence {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+            ..*a
+        });
+    }
+    if let (Some(a), Some(b)) = (sep_a, sep_b) {
+        child.insert(Separation {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+            distance: mutation.inherit(a.distance, b.distance, rng),
+            ..*a
+        });
+    }
+    if let (Some(a), Some(b)) = (ali_a, ali_b) {
+        child.insert(Alignment {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+            ..*a
+        });
+    }
+    if let (Some(a), Some(b)) = (bound_a, bound_b) {
+        child.insert(WorldBound {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+        });
+    }
+    if let (Some(a), Some(b)) = (avoid_a, avoid_b) {
+        child.insert(ObstacleAvoidance {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+            ..*a
+        });
+    }
+    if let (Some(a), Some(b)) = (des_a, des_b) {
+        child.insert(DesiredVelocity {
+            factor: mutation.inherit(a.factor, b.factor, rng),
+        });
+    }
+
+    child.id()
+}
+
+/// Despawns boids past their lifespan.
+///
+/// Dead boids are also removed from the spatial partition and from every perception list so
+/// no system can look them up before the next perception pass.
+pub fn death_system(
+    mut commands: Commands,
+    query: Query<(Entity, &Age)>,
+    mut perceptions: Query<&mut Perception>,
+    mut space: ResMut<SpatialRes>,
+    mut events: EventWriter<DeathEvent>,
+) {
+    let dead: HashSet<Entity> = query
+        .iter()
+        .filter(|(_, age)| age.lifespan > 0.0 && age.age >= age.lifespan)
+        .map(|(entity, age)| {
+            events.send(DeathEvent {
+                entity,
+                age: age.age,
+            });
+            entity
+        })
+        .collect();
+
+    if dead.is_empty() {
+        return;
+    }
+
+    for &entity in &dead {
+        space.space.remove(entity);
+        commands.entity(entity).despawn_recursive();
+    }
+
+    for mut per in &mut perceptions {
+        per.list.retain(|e| !dead.contains(e));
+    }
+}
@@ -3,14 +3,15 @@ extern crate bevy;
 use bevy::math::vec3;
 use bevy::pbr::CascadeShadowConfigBuilder;
 use bevy::prelude::*;
-use bevy_flock::behaviours::avoidance::ObstacleAvoidance;
+use bevy_flock::behaviours::avoidance::{ObstacleAvoidance, ObstacleAvoidancePlugin};
 use bevy_flock::behaviours::{
-    Alignment, BoidsPlugin, Coherence, DesiredVelocity, Separation, WorldBound,
+    Alignment, AltitudePreference, BoidsPlugin, Coherence, DesiredVelocity, Separation, WorldBound,
 };
 use bevy_flock::boid::Boid;
-use bevy_flock::flock::{random_direction, random_transform, BoidsRules, GameArea};
+use bevy_flock::flock::{random_direction, BoidsRules, GameArea};
 use bevy_flock::perception::Perception;
 use bevy_flock::physics::Velocity;
+use bevy_flock::terrain::Heightmap;
 use bevy_flock::{BaseFlockBundle, SteeringPlugin};
 use bevy_flycam::{FlyCam, NoCameraPlayerPlugin};
 
@@ -22,11 +23,15 @@ fn main() {
         }))
         .add_plugin(SteeringPlugin)
         .add_plugin(BoidsPlugin)
+        .add_plugin(ObstacleAvoidancePlugin)
         .add_plugin(NoCameraPlayerPlugin)
         .insert_resource(GameArea {
             offset: Vec3::ZERO,
-            area: shape::Box::new(100.0, 100.0, 100.0),
+            area: shape::Box::new(100.0, 100.0, 100.0).into(),
+            mode: default(),
         })
+        // Matches the ground plane spawned in setup
+        .insert_resource(Heightmap::flat(Vec2::ZERO, Vec2::splat(200.0), -50.0))
         .insert_resource(BoidsRules {
             desired_speed: 50.0,
             max_force: 1000.0,
@@ -66,10 +71,11 @@ fn setup(
             acc: Default::default(),
             mesh: SceneBundle {
                 scene: asset_server.load("models/bird.gltf#Scene0"),
-                transform: random_transform(rules.area),
+                transform: rules.random_transform(),
                 ..default()
             },
             integrator: Default::default(),
+            scale: Default::default(),
         };
 
         commands
@@ -78,14 +84,31 @@ fn setup(
                 range: perception,
                 ..default()
             })
-            .insert(Coherence { factor: 4.0 })
+            .insert(Coherence {
+                factor: 4.0,
+                ..default()
+            })
             .insert(Separation {
                 factor: 8.0,
                 distance: 0.75,
+                ..default()
+            })
+            .insert(Alignment {
+                factor: 2.0,
+                ..default()
             })
-            .insert(Alignment { factor: 2.0 })
             .insert(WorldBound { factor: 4.0 })
-            .insert(ObstacleAvoidance { factor: 50.0 })
+            // Keeps the flock in the lower part of the area, close to the ground
+            .insert(AltitudePreference {
+                factor: 4.0,
+                min: 5.0,
+                max: 40.0,
+                ..default()
+            })
+            .insert(ObstacleAvoidance {
+                factor: 50.0,
+                ..default()
+            })
             .insert(DesiredVelocity { factor: 0.1 });
     }
 
@@ -93,7 +116,7 @@ fn setup(
         mesh: meshes.add(shape::Plane::from_size(200.0).into()),
         material: materials.add(Color::rgb(0.3, 0.5, 0.3).into()),
         transform: Transform {
-            translation: vec3(0., -100., 0.),
+            translation: vec3(0., -50., 0.),
             //rotation: Quat::from_rotation_x(0.0),
             ..default()
         },
@@ -0,0 +1,490 @@
+use crate::behaviours::roosting::{resting_system, Resting};
+use crate::flock::{BoidsRules, LocalRules};
+use crate::perception::Perception;
+use crate::physics::{force_application_system, velocity_system, Velocity};
+use crate::BoidStage;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+const EPSILON: f32 = 1e-5;
+
+/// Makes a boid take part in the ORCA solver
+#[derive(Component, Clone, Copy, Debug)]
+pub struct OrcaAgent {
+    pub radius: f32,
+    /// How many seconds ahead collisions with neighbours are avoided
+    pub time_horizon: f32,
+    /// Speed limit of the solver, None uses `BoidsRules::max_velocity`
+    pub max_speed: Option<f32>,
+}
+
+impl Default for OrcaAgent {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            time_horizon: 2.0,
+            max_speed: None,
+        }
+    }
+}
+
+/// Optimal reciprocal collision avoidance (ORCA), following the 3D formulation of RVO2-3D.
+///
+/// Each neighbour gives a half-space of velocities that keep the two boids apart for
+/// `time_horizon` seconds. The solver picks the velocity closest to the preferred one that lies
+/// in all of them, or the least bad one when the half-spaces leave no room.
+pub struct OrcaPlugin;
+
+impl Plugin for OrcaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            orca_system
+                .in_set(BoidStage::ForceApplication)
+                .after(force_application_system)
+                .before(resting_system)
+                .before(velocity_system),
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Plane {
+    point: Vec3,
+    normal: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Line {
+    point: Vec3,
+    direction: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Snapshot {
+    position: Vec3,
+    velocity: Vec3,
+    radius: Option<f32>,
+}
+
+/// Replaces the velocity of every agent by the closest collision-free one, runs once the forces
+/// are applied and before the boids move. Perched boids are left to the roosting systems.
+pub fn orca_system(
+    agents: Query<(Entity, &Perception, &OrcaAgent, Option<&LocalRules>), Without<Resting>>,
+    mut boids: Query<(Entity, &Transform, &mut Velocity, Option<&OrcaAgent>)>,
+    rules: Res<BoidsRules>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+
+    // Every agent solves against the velocities from before any of them changed
+    let snapshot: HashMap<Entity, Snapshot> = boids
+        .iter()
+        .map(|(e, tf, vel, agent)| {
+            let snapshot = Snapshot {
+                position: tf.translation,
+                velocity: vel.vec,
+                radius: agent.map(|a| a.radius),
+            };
+            (e, snapshot)
+        })
+        .collect();
+
+    let mut planes = Vec::new();
+    for (entity, per, agent, local) in &agents {
+        let Some(own) = snapshot.get(&entity) else {
+            continue;
+        };
+
+        planes.clear();
+        planes.extend(
+            per.list
+                .iter()
+                .filter(|&&e| e != entity)
+                .filter_map(|e| snapshot.get(e))
+                .map(|other| orca_plane(own, agent, other, delta)),
+        );
+
+        let max_speed = agent.max_speed.unwrap_or(rules.local(local).max_velocity);
+        let velocity = solve(&planes, max_speed, own.velocity);
+
+        if let Ok((_, _, mut vel, _)) = boids.get_mut(entity) {
+            vel.vec = velocity;
+        }
+    }
+}
+
+/// Half-space of velocities that avoid `other` for the agent's time horizon
+fn orca_plane(own: &Snapshot, agent: &OrcaAgent, other: &Snapshot, delta: f32) -> Plane {
+    let relative_position = other.position - own.position;
+    let relative_velocity = own.velocity - other.velocity;
+    let dist_sq = relative_position.length_squared();
+    let combined_radius = agent.radius + other.radius.unwrap_or(agent.radius);
+    let combined_radius_sq = combined_radius * combined_radius;
+    let inv_time_horizon = 1.0 / agent.time_horizon.max(EPSILON);
+
+    let (u, normal) = if dist_sq > combined_radius_sq {
+        // Vector from the cut-off centre to the relative velocity
+        let w = relative_velocity - relative_position * inv_time_horizon;
+        let w_length_sq = w.length_squared();
+        let dot = w.dot(relative_position);
+
+        if dot < 0.0 && dot * dot > combined_radius_sq * w_length_sq {
+            // Project on the cut-off sphere
+            let w_length = w_length_sq.sqrt();
+            let unit_w = w / w_length;
+            (
+                (combined_radius * inv_time_horizon - w_length) * unit_w,
+                unit_w,
+            )
+        } else {
+            // Project on the cone
+            let a = dist_sq;
+            let b = relative_position.dot(relative_velocity);
+            let c = relative_velocity.length_squared()
+                - relative_position.cross(relative_velocity).length_squared()
+                    / (dist_sq - combined_radius_sq);
+            let t = (b + (b * b - a * c).max(0.0).sqrt()) / a;
+            let w = relative_velocity - relative_position * t;
+            let w_length = w.length();
+            let unit_w = w
+                .try_normalize()
+                .unwrap_or_else(|| sidestep(relative_position));
+            ((combined_radius * t - w_length) * unit_w, unit_w)
+        }
+    } else {
+        // Already overlapping, separate within this frame
+        let inv_time_step = 1.0 / delta;
+        let w = relative_velocity - relative_position * inv_time_step;
+        let w_length = w.length();
+        let unit_w = w
+            .try_normalize()
+            .unwrap_or_else(|| sidestep(relative_position));
+        (
+            (combined_radius * inv_time_step - w_length) * unit_w,
+            unit_w,
+        )
+    };
+
+    // Neighbours taking part in ORCA avoid half of the collision, the others none of it
+    let responsibility = if other.radius.is_some() { 0.5 } else { 1.0 };
+
+    Plane {
+        point: own.velocity + u * responsibility,
+        normal,
+    }
+}
+
+/// Direction to dodge a neighbour that is dead ahead, kept in the plane the boids fly in
+fn sidestep(relative_position: Vec3) -> Vec3 {
+    relative_position
+        .cross(Vec3::Z)
+        .try_normalize()
+        .unwrap_or_else(|| relative_position.any_orthonormal_vector())
+}
+
+fn solve(planes: &[Plane], max_speed: f32, preferred: Vec3) -> Vec3 {
+    let mut result = Vec3::ZERO;
+    let fail = linear_program_3(planes, max_speed, preferred, false, &mut result);
+    if fail < planes.len() {
+        linear_program_4(planes, fail, max_speed, &mut result);
+    }
+    result
+}
+
+/// Best velocity on a line, constrained by the planes before `plane_no`
+fn linear_program_1(
+    planes: &[Plane],
+    plane_no: usize,
+    line: &Line,
+    radius: f32,
+    opt_velocity: Vec3,
+    direction_opt: bool,
+    result: &mut Vec3,
+) -> bool {
+    let dot = line.point.dot(line.direction);
+    let discriminant = dot * dot + radius * radius - line.point.length_squared();
+    if discriminant < 0.0 {
+        // The max speed sphere does not reach the line
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let mut t_left = -dot - sqrt_discriminant;
+    let mut t_right = -dot + sqrt_discriminant;
+
+    for plane in &planes[..plane_no] {
+        let numerator = (plane.point - line.point).dot(plane.normal);
+        let denominator = line.direction.dot(plane.normal);
+
+        if denominator * denominator <= EPSILON {
+            // The line is parallel to the plane
+            if numerator > 0.0 {
+                return false;
+            }
+            continue;
+        }
+
+        let t = numerator / denominator;
+        if denominator >= 0.0 {
+            t_left = t_left.max(t);
+        } else {
+            t_right = t_right.min(t);
+        }
+
+        if t_left > t_right {
+            return false;
+        }
+    }
+
+    let t = if direction_opt {
+        if opt_velocity.dot(line.direction) > 0.0 {
+            t_right
+        } else {
+            t_left
+        }
+    } else {
+        line.direction
+            .dot(opt_velocity - line.point)
+            .max(t_left)
+            .min(t_right)
+    };
+    *result = line.point + line.direction * t;
+    true
+}
+
+/// Best velocity on plane `plane_no`, constrained by the planes before it
+fn linear_program_2(
+    planes: &[Plane],
+    plane_no: usize,
+    radius: f32,
+    opt_velocity: Vec3,
+    direction_opt: bool,
+    result: &mut Vec3,
+) -> bool {
+    let plane = planes[plane_no];
+    let plane_dist = plane.point.dot(plane.normal);
+    let plane_dist_sq = plane_dist * plane_dist;
+    let radius_sq = radius * radius;
+
+    if plane_dist_sq > radius_sq {
+        // The max speed sphere does not reach the plane
+        return false;
+    }
+
+    let plane_radius_sq = radius_sq - plane_dist_sq;
+    let plane_center = plane.normal * plane_dist;
+
+    if direction_opt {
+        let plane_opt = opt_velocity - plane.normal * opt_velocity.dot(plane.normal);
+        let plane_opt_length_sq = plane_opt.length_squared();
+
+        *result = if plane_opt_length_sq <= EPSILON {
+            plane_center
+        } else {
+            plane_center + plane_opt * (plane_radius_sq / plane_opt_length_sq).sqrt()
+        };
+    } else {
+        *result = opt_velocity + plane.normal * (plane.point - opt_velocity).dot(plane.normal);
+
+        if result.length_squared() > radius_sq {
+            let plane_result = *result - plane_center;
+            let plane_result_length_sq = plane_result.length_squared().max(EPSILON);
+            *result =
+                plane_center + plane_result * (plane_radius_sq / plane_result_length_sq).sqrt();
+        }
+    }
+
+    for i in 0..plane_no {
+        let other = planes[i];
+        if other.normal.dot(other.point - *result) <= 0.0 {
+            continue;
+        }
+
+        // The result breaks an earlier plane, search the line where both planes meet
+        let cross = other.normal.cross(plane.normal);
+        if cross.length_squared() <= EPSILON {
+            // Parallel planes with no room between them
+            return false;
+        }
+
+        let direction = cross.normalize();
+        let line_normal = direction.cross(plane.normal);
+        let line = Line {
+            point: plane.point
+                + line_normal
+                    * ((other.point - plane.point).dot(other.normal)
+                        / line_normal.dot(other.normal)),
+            direction,
+        };
+
+        if !linear_program_1(
+            planes,
+            i,
+            &line,
+            radius,
+            opt_velocity,
+            direction_opt,
+            result,
+        ) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Best velocity within all planes, returns the index of the first plane it failed on
+fn linear_program_3(
+    planes: &[Plane],
+    radius: f32,
+    opt_velocity: Vec3,
+    direction_opt: bool,
+    result: &mut Vec3,
+) -> usize {
+    *result = if direction_opt {
+        // The optimisation direction is a unit vector
+        opt_velocity * radius
+    } else if opt_velocity.length_squared() > radius * radius {
+        opt_velocity.normalize() * radius
+    } else {
+        opt_velocity
+    };
+
+    for i in 0..planes.len() {
+        if planes[i].normal.dot(planes[i].point - *result) > 0.0 {
+            let previous = *result;
+            if !linear_program_2(planes, i, radius, opt_velocity, direction_opt, result) {
+                *result = previous;
+                return i;
+            }
+        }
+    }
+
+    planes.len()
+}
+
+/// No velocity satisfies every plane, minimise the largest violation instead
+fn linear_program_4(planes: &[Plane], begin: usize, radius: f32, result: &mut Vec3) {
+    let mut distance = 0.0;
+    let mut projected = Vec::new();
+
+    for i in begin..planes.len() {
+        let plane = planes[i];
+        if plane.normal.dot(plane.point - *result) <= distance {
+            continue;
+        }
+
+        projected.clear();
+        for other in &planes[..i] {
+            let cross = other.normal.cross(plane.normal);
+
+            let point = if cross.length_squared() <= EPSILON {
+                if plane.normal.dot(other.normal) > 0.0 {
+                    // Same direction, already covered by this plane
+                    continue;
+                }
+                (plane.point + other.point) * 0.5
+            } else {
+                let line_normal = cross.cross(plane.normal);
+                plane.point
+                    + line_normal
+                        * ((other.point - plane.point).dot(other.normal)
+                            / line_normal.dot(other.normal))
+            };
+
+            projected.push(Plane {
+                point,
+                normal: (other.normal - plane.normal).normalize_or_zero(),
+            });
+        }
+
+        let previous = *result;
+        if linear_program_3(&projected, radius, plane.normal, true, result) < projected.len() {
+            // Only fails from rounding errors, keep the previous result
+            *result = previous;
+        }
+
+        distance = plane.normal.dot(plane.point - *result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::vec3;
+
+    const AGENT: OrcaAgent = OrcaAgent {
+        radius: 0.5,
+        time_horizon: 3.0,
+        max_speed: None,
+    };
+    const DELTA: f32 = 1.0 / 60.0;
+
+    fn snapshot(position: Vec3, velocity: Vec3) -> Snapshot {
+        Snapshot {
+            position,
+            velocity,
+            radius: Some(AGENT.radius),
+        }
+    }
+
+    /// Both agents solve against each other, as `orca_system` does
+    fn reciprocal(a: &Snapshot, b: &Snapshot) -> (Vec3, Vec3) {
+        let va = solve(&[orca_plane(a, &AGENT, b, DELTA)], 10.0, a.velocity);
+        let vb = solve(&[orca_plane(b, &AGENT, a, DELTA)], 10.0, b.velocity);
+        (va, vb)
+    }
+
+    /// Smallest distance between the agents while they solve and move for a few seconds
+    fn closest_distance(mut a: Snapshot, mut b: Snapshot) -> f32 {
+        let mut closest = f32::INFINITY;
+        for _ in 0..300 {
+            (a.velocity, b.velocity) = reciprocal(&a, &b);
+            a.position += a.velocity * DELTA;
+            b.position += b.velocity * DELTA;
+            closest = closest.min(a.position.distance(b.position));
+        }
+        closest
+    }
+
+    #[test]
+    fn head_on_agents_sidestep_each_other() {
+        let a = snapshot(Vec3::ZERO, Vec3::X);
+        let b = snapshot(Vec3::X * 4.0, Vec3::NEG_X);
+
+        // Both take half of the avoidance
+        let (va, vb) = reciprocal(&a, &b);
+        assert!(va.y != 0.0);
+        assert!(va.abs_diff_eq(-vb, 1e-5));
+
+        assert!(closest_distance(a, b) >= 2.0 * AGENT.radius - 1e-2);
+    }
+
+    #[test]
+    fn crossing_agents_avoid_each_other() {
+        // Without avoidance both reach (2, 0, 0) after 2 seconds
+        let a = snapshot(Vec3::ZERO, Vec3::X);
+        let b = snapshot(vec3(2.0, -2.0, 0.0), Vec3::Y);
+
+        assert!(closest_distance(a, b) >= 2.0 * AGENT.radius - 1e-2);
+    }
+
+    #[test]
+    fn agents_far_apart_keep_their_velocity() {
+        let a = snapshot(Vec3::ZERO, Vec3::X);
+        let b = snapshot(Vec3::Y * 10.0, Vec3::X);
+        let (va, vb) = reciprocal(&a, &b);
+
+        assert!(va.abs_diff_eq(a.velocity, 1e-5));
+        assert!(vb.abs_diff_eq(b.velocity, 1e-5));
+    }
+
+    #[test]
+    fn solution_respects_the_speed_limit() {
+        let velocity = solve(&[], 2.0, Vec3::X * 5.0);
+        assert!(velocity.abs_diff_eq(Vec3::X * 2.0, 1e-5));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::physics::ColliderFilters;
 use crate::spatial::partition::SpatialRes;
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
@@ -9,12 +10,17 @@ pub struct Perception {
 }
 
 pub fn rapier_perception_system(
-    mut query: Query<(&mut Perception, &Transform)>,
+    mut query: Query<(
+        Entity,
+        &mut Perception,
+        &Transform,
+        Option<&ColliderFilters>,
+    )>,
     rapier: Res<RapierContext>,
+    filters: Res<ColliderFilters>,
 ) {
-    let filter = QueryFilter::default();
-
-    for (mut per, tf) in &mut query {
+    for (entity, mut per, tf, local) in &mut query {
+        let filter = filters.local(local).perception_filter(entity);
         let mut list = Vec::new();
         let shape = Collider::ball(per.range);
         let pos = tf.translation;
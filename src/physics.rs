@@ -1,4 +1,6 @@
-use crate::flock::BoidsRules;
+use crate::boid::Boid;
+use crate::energy::Energy;
+use crate::flock::{BoidsRules, LocalRules};
 use crate::velocity_angle;
 use bevy::ecs::entity::Entity;
 use bevy::prelude::*;
@@ -15,6 +17,72 @@ pub struct Acceleration {
     pub vec: Vec3,
 }
 
+/// Group boid colliders are put in, so that the default filters can tell them from obstacles
+pub const BOID_GROUP: Group = Group::GROUP_32;
+
+/// Which rapier colliders boids take into account.
+///
+/// Inserted as a resource it applies to every boid, inserted as a component it gives a boid,
+/// or every boid of a flock, its own filters. By default other boids are perceived but never
+/// avoided as obstacles.
+#[derive(Resource, Component, Clone, Copy, Debug)]
+pub struct ColliderFilters {
+    /// Groups of the colliders avoided as obstacles
+    pub obstacles: CollisionGroups,
+    /// Groups of the colliders perceived by `rapier_perception_system`
+    pub perception: CollisionGroups,
+    /// Whether sensors count as obstacles
+    pub obstacle_sensors: bool,
+}
+
+impl Default for ColliderFilters {
+    fn default() -> Self {
+        Self {
+            obstacles: CollisionGroups::new(Group::ALL, !BOID_GROUP),
+            perception: CollisionGroups::new(Group::ALL, BOID_GROUP),
+            obstacle_sensors: false,
+        }
+    }
+}
+
+impl ColliderFilters {
+    /// The filters a boid uses, its own when it has some
+    pub fn local(&self, local: Option<&ColliderFilters>) -> ColliderFilters {
+        local.copied().unwrap_or(*self)
+    }
+
+    /// Obstacles seen by `entity`, never including its own collider
+    pub fn obstacle_filter(&self, entity: Entity) -> QueryFilter<'static> {
+        let filter = QueryFilter::default()
+            .groups(self.obstacles)
+            .exclude_collider(entity);
+
+        if self.obstacle_sensors {
+            filter
+        } else {
+            filter.exclude_sensors()
+        }
+    }
+
+    /// Colliders perceived by `entity`, never including its own collider
+    pub fn perception_filter(&self, entity: Entity) -> QueryFilter<'static> {
+        QueryFilter::default()
+            .groups(self.perception)
+            .exclude_collider(entity)
+    }
+}
+
+type UngroupedBoid = (With<Boid>, Added<Collider>, Without<CollisionGroups>);
+
+/// Puts new boid colliders in `BOID_GROUP`, unless they were given groups of their own
+pub fn boid_group_system(mut commands: Commands, query: Query<Entity, UngroupedBoid>) {
+    for entity in &query {
+        commands
+            .entity(entity)
+            .insert(CollisionGroups::new(BOID_GROUP, Group::ALL));
+    }
+}
+
 pub fn rotation_system(mut query: Query<(&mut Transform, &Velocity)>) {
     for (mut tf, vel) in &mut query {
         tf.rotation = Quat::from_rotation_z(velocity_angle(&vel.vec));
@@ -22,14 +90,26 @@ pub fn rotation_system(mut query: Query<(&mut Transform, &Velocity)>) {
 }
 
 pub fn force_application_system(
-    mut query: Query<(&mut Velocity, &mut Acceleration)>,
+    mut query: Query<(
+        &mut Velocity,
+        &mut Acceleration,
+        Option<&Energy>,
+        Option<&LocalRules>,
+    )>,
     boid_rules: Res<BoidsRules>,
     time: Res<Time>,
 ) {
-    for (mut vel, mut acc) in &mut query {
+    for (mut vel, mut acc, energy, local) in &mut query {
+        let boid_rules = boid_rules.local(local);
+
+        // Tired boids can neither steer as hard nor fly as fast
+        let scale = energy.map_or(1.0, |e| e.limit_scale());
+        let max_force = boid_rules.max_force * scale;
+        let max_velocity = boid_rules.max_velocity * scale;
+
         // Clamp max acceleration
-        if acc.vec.length() > boid_rules.max_force {
-            acc.vec = acc.vec.normalize_or_zero().mul(boid_rules.max_force);
+        if acc.vec.length() > max_force {
+            acc.vec = acc.vec.normalize_or_zero().mul(max_force);
         }
 
         // Apply acceleration changes to velocity.
@@ -37,8 +117,8 @@ pub fn force_application_system(
         acc.vec = Vec3::ZERO;
 
         // Clamp velocity before releasing to other systems
-        if vel.vec.length() > boid_rules.max_velocity {
-            vel.vec = vel.vec.normalize_or_zero().mul(boid_rules.max_velocity);
+        if vel.vec.length() > max_velocity {
+            vel.vec = vel.vec.normalize_or_zero().mul(max_velocity);
         }
     }
 }
@@ -56,15 +136,16 @@ pub fn velocity_system(mut query: Query<(&mut Transform, &Velocity)>, time: Res<
 /// * `context`: Rapier context
 /// * `perception`: How far to look
 /// * `location`: Where to look from
+/// * `filter`: Which colliders count as obstacles
 ///
 /// returns: Vec<Entity, Global>
 pub fn find_obstacles_in_range(
     context: &Res<RapierContext>,
     perception: f32,
     location: Vec3,
+    filter: QueryFilter,
 ) -> Vec<Entity> {
     let shape = Collider::ball(perception);
-    let filter = QueryFilter::default();
     let mut entities = Vec::new();
 
     context.intersections_with_shape(location, Rot::default(), &shape, filter, |entity| {
@@ -74,6 +155,39 @@ pub fn find_obstacles_in_range(
     return entities;
 }
 
+/// Sweeps a sphere from the boid along a direction
+///
+/// # Arguments
+///
+/// * `context`: Rapier context
+/// * `from_position`: Where the sweep starts
+/// * `direction`: Unit direction of the sweep
+/// * `radius`: Radius of the swept sphere
+/// * `distance`: How far to sweep
+/// * `filter`: Which colliders count as obstacles
+///
+/// returns: Option<(f32, Vec3)>, the distance to the first hit and the surface normal there
+pub fn cast_feeler(
+    context: &Res<RapierContext>,
+    from_position: Vec3,
+    direction: Vec3,
+    radius: f32,
+    distance: f32,
+    filter: QueryFilter,
+) -> Option<(f32, Vec3)> {
+    let shape = Collider::ball(radius);
+
+    let (_, hit) = context.cast_shape(
+        from_position,
+        Rot::default(),
+        direction,
+        &shape,
+        distance,
+        filter,
+    )?;
+    Some((hit.toi, hit.normal1))
+}
+
 /// Finds the closest point to a boid projected onto a shape.
 /// Typically used to measure steering pressure by obstacles
 ///
@@ -83,15 +197,15 @@ pub fn find_obstacles_in_range(
 /// * `from_position`: The position of the boid
 /// * `target_collider`: The obstacle in range
 ///
-/// returns: Vec2
+/// returns: Option<Vec3>, None when the collider no longer exists
 pub fn find_nearest_point_on_collider(
     context: &Res<RapierContext>,
     from_position: Vec3,
     target_entity: Entity,
-) -> Vec3 {
+) -> Option<Vec3> {
     let binding = |e| e == target_entity;
     let filter = QueryFilter::default().predicate(&binding);
 
-    let (_, pp) = context.project_point(from_position, true, filter).unwrap();
-    return pp.point;
+    let (_, pp) = context.project_point(from_position, true, filter)?;
+    return Some(pp.point);
 }
@@ -0,0 +1,40 @@
+use crate::physics::Velocity;
+use bevy::prelude::*;
+
+/// Marks an entity the boids should react to as a threat
+#[derive(Component, Default, Clone, Copy)]
+pub struct Predator;
+
+#[derive(Clone, Copy)]
+pub struct Threat {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub distance: f32,
+}
+
+/// Finds the closest predator within range of a position
+///
+/// # Arguments
+///
+/// * `position`: Where to look from
+/// * `range`: How far to look
+/// * `predators`: All predators in the world
+///
+/// returns: Option<Threat>
+pub fn nearest_threat(
+    position: Vec3,
+    range: f32,
+    predators: &Query<(Entity, &Transform, Option<&Velocity>), With<Predator>>,
+) -> Option<Threat> {
+    predators
+        .iter()
+        .map(|(entity, tf, vel)| Threat {
+            entity,
+            position: tf.translation,
+            velocity: vel.map_or(Vec3::ZERO, |v| v.vec),
+            distance: tf.translation.distance(position),
+        })
+        .filter(|threat| threat.distance <= range)
+        .min_by(|a, b| a.distance.total_cmp(&b.distance))
+}
@@ -75,6 +75,12 @@ impl SpatialPartition for IndexPartition {
         }
     }
 
+    fn remove(&mut self, entity: Entity) {
+        for list in self.map.values_mut() {
+            list.retain(|(e, _)| *e != entity);
+        }
+    }
+
     fn clear(&mut self) {
         self.map.clear();
     }
@@ -1,3 +1,4 @@
+use crate::behaviours::Resting;
 use crate::boid::Boid;
 use bevy::prelude::*;
 
@@ -5,6 +6,7 @@ pub trait SpatialPartition {
     fn get_nearby_ent(&self, origin: &Vec3, perception: f32) -> Vec<Entity>;
     fn insert(&mut self, ent: Entity, position: &Vec3);
     fn bulk_insert(&mut self, bulk: Vec<(Entity, Vec3)>);
+    fn remove(&mut self, ent: Entity);
     fn clear(&mut self);
 }
 
@@ -16,16 +18,17 @@ pub struct SpatialRes {
 /// The system is meant to gather all the behaviours so they can be stored in a space data structure
 /// for efficient retrieval at a later date
 ///
-/// The resource must implement the SpatialPartition trait
+/// The resource must implement the SpatialPartition trait. Resting boids are left out.
 pub fn spatial_hash_system(
-    query: Query<(Entity, &Transform), With<Boid>>,
+    query: Query<(Entity, &Transform, Option<&Resting>), With<Boid>>,
     mut res: ResMut<SpatialRes>,
 ) {
     res.space.clear();
 
     let list = query
         .into_iter()
-        .map(|(e, &tf)| (e, tf.translation))
+        .filter(|(_, _, resting)| resting.is_none())
+        .map(|(e, &tf, _)| (e, tf.translation))
         .collect();
 
     res.space.bulk_insert(list);
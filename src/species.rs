@@ -0,0 +1,112 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+
+/// Species of a boid, an index into the `InteractionMatrix`. Boids without one are species 0.
+#[derive(Component, Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Species(pub usize);
+
+/// How strongly a boid reacts to a neighbour of some species, negative values invert the behaviour
+#[derive(Clone, Copy, Debug)]
+pub struct InteractionWeights {
+    pub coherence: f32,
+    pub separation: f32,
+    pub alignment: f32,
+}
+
+impl Default for InteractionWeights {
+    fn default() -> Self {
+        Self {
+            coherence: 1.0,
+            separation: 1.0,
+            alignment: 1.0,
+        }
+    }
+}
+
+/// Weights for each pair of species, read as how species `a` reacts to species `b`.
+///
+/// Pairs outside the matrix use the default weights, so an empty matrix flocks every boid
+/// with every other.
+#[derive(Resource, Default, Clone, Debug)]
+pub struct InteractionMatrix {
+    species: usize,
+    /// Row major, `species * species` entries
+    weights: Vec<InteractionWeights>,
+}
+
+impl InteractionMatrix {
+    /// A matrix where every species flocks with every other
+    pub fn new(species: usize) -> Self {
+        Self {
+            species,
+            weights: vec![InteractionWeights::default(); species * species],
+        }
+    }
+
+    /// A matrix from row major weights, None unless there are `species * species` of them
+    pub fn from_weights(species: usize, weights: Vec<InteractionWeights>) -> Option<Self> {
+        (weights.len() == species * species).then_some(Self { species, weights })
+    }
+
+    pub fn species(&self) -> usize {
+        self.species
+    }
+
+    /// Species only flock with their own kind and ignore the others
+    pub fn segregated(species: usize) -> Self {
+        let mut matrix = Self::new(species);
+        for a in 0..species {
+            for b in (0..species).filter(|&b| b != a) {
+                matrix.set(
+                    a,
+                    b,
+                    InteractionWeights {
+                        coherence: 0.0,
+                        separation: 1.0,
+                        alignment: 0.0,
+                    },
+                );
+            }
+        }
+        matrix
+    }
+
+    pub fn get(&self, a: usize, b: usize) -> InteractionWeights {
+        if a < self.species && b < self.species {
+            self.weights[a * self.species + b]
+        } else {
+            InteractionWeights::default()
+        }
+    }
+
+    pub fn set(&mut self, a: usize, b: usize, weights: InteractionWeights) {
+        if a < self.species && b < self.species {
+            self.weights[a * self.species + b] = weights;
+        }
+    }
+
+    /// Sets how `a` reacts to `b` and `b` to `a`
+    pub fn set_symmetric(&mut self, a: usize, b: usize, weights: InteractionWeights) {
+        self.set(a, b, weights);
+        self.set(b, a, weights);
+    }
+}
+
+/// Looks up the interaction weights between two boids
+#[derive(SystemParam)]
+pub struct SpeciesLookup<'w, 's> {
+    species: Query<'w, 's, &'static Species>,
+    matrix: Res<'w, InteractionMatrix>,
+}
+
+impl SpeciesLookup<'_, '_> {
+    pub fn species(&self, entity: Entity) -> usize {
+        self.species.get(entity).map_or(0, |s| s.0)
+    }
+
+    /// How `entity` reacts to `neighbour`
+    pub fn weights(&self, entity: Entity, neighbour: Entity) -> InteractionWeights {
+        self.matrix
+            .get(self.species(entity), self.species(neighbour))
+    }
+}
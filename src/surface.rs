@@ -0,0 +1,175 @@
+use crate::behaviours::bounds::boundary_response_system;
+use crate::physics::{force_application_system, velocity_system, Velocity};
+use crate::terrain::Heightmap;
+use crate::BoidStage;
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+/// A 2-manifold boids can be constrained to
+#[derive(Clone, Copy, Debug)]
+pub enum Surface {
+    Sphere {
+        centre: Vec3,
+        radius: f32,
+    },
+    /// The `Heightmap` resource
+    Heightfield,
+    /// The rapier collider on `collider`, usually a trimesh. It is searched up to `probe` away
+    /// from the boid along its last surface normal
+    Mesh {
+        collider: Entity,
+        probe: f32,
+    },
+}
+
+/// Keeps a boid on a surface: its velocity stays in the tangent plane and its neighbours are
+/// measured along the surface
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SurfaceBound {
+    pub surface: Surface,
+    /// Height kept above the surface
+    pub altitude: f32,
+    /// Surface normal under the boid, updated as it moves
+    pub normal: Vec3,
+}
+
+impl SurfaceBound {
+    pub fn new(surface: Surface) -> Self {
+        Self {
+            surface,
+            altitude: 0.0,
+            normal: Vec3::Y,
+        }
+    }
+
+    /// `vector` in the tangent plane, keeping its length
+    pub fn tangent(&self, vector: Vec3) -> Vec3 {
+        let tangent = vector - self.normal * vector.dot(self.normal);
+        tangent.normalize_or_zero() * vector.length()
+    }
+
+    /// Offset from `from` to `to` along the surface
+    pub fn offset(&self, from: Vec3, to: Vec3) -> Vec3 {
+        match self.surface {
+            Surface::Sphere { centre, .. } => {
+                let (a, b) = (from - centre, to - centre);
+                let (na, nb) = (a.normalize_or_zero(), b.normalize_or_zero());
+                let cos = na.dot(nb).clamp(-1.0, 1.0);
+                // Great circle distance at the boid's own height
+                (nb - na * cos).normalize_or_zero() * cos.acos() * a.length()
+            }
+            Surface::Heightfield | Surface::Mesh { .. } => self.tangent(to - from),
+        }
+    }
+
+    /// Closest point on the surface and the normal there
+    fn project(
+        &self,
+        point: Vec3,
+        heightmap: Option<&Heightmap>,
+        rapier: &RapierContext,
+    ) -> Option<(Vec3, Vec3)> {
+        match self.surface {
+            Surface::Sphere { centre, radius } => {
+                let normal = (point - centre).try_normalize().unwrap_or(self.normal);
+                Some((centre + normal * radius, normal))
+            }
+            Surface::Heightfield => {
+                let heightmap = heightmap?;
+                let height = heightmap.height_at(point.x, point.z)?;
+                let normal = heightmap.normal_at(point.x, point.z)?;
+                Some((Vec3::new(point.x, height, point.z), normal))
+            }
+            Surface::Mesh { collider, probe } => {
+                let predicate = |e| e == collider;
+                let filter = QueryFilter::new().predicate(&predicate);
+                let origin = point + self.normal * probe;
+
+                if let Some((_, hit)) =
+                    rapier.cast_ray_and_get_normal(origin, -self.normal, probe * 2.0, false, filter)
+                {
+                    // Trimesh normals may face either side, keep the side the boid is on
+                    let normal = if hit.normal.dot(self.normal) < 0.0 {
+                        -hit.normal
+                    } else {
+                        hit.normal
+                    };
+                    return Some((hit.point, normal.normalize_or_zero()));
+                }
+
+                // Ran off an edge or a sharp fold, fall back to the closest point
+                let (_, projection) = rapier.project_point(point, false, filter)?;
+                Some((projection.point, self.normal))
+            }
+        }
+    }
+}
+
+/// Offsets between boids, measured along the surface for boids bound to one
+#[derive(SystemParam)]
+pub struct SurfaceLookup<'w, 's> {
+    bounds: Query<'w, 's, &'static SurfaceBound>,
+}
+
+impl SurfaceLookup<'_, '_> {
+    /// Offset from `entity` at `from` to a neighbour at `to`
+    pub fn offset(&self, entity: Entity, from: Vec3, to: Vec3) -> Vec3 {
+        match self.bounds.get(entity) {
+            Ok(bound) => bound.offset(from, to),
+            Err(_) => to - from,
+        }
+    }
+
+    /// A neighbour's `vector` brought into the tangent plane of `entity`
+    pub fn tangent(&self, entity: Entity, vector: Vec3) -> Vec3 {
+        match self.bounds.get(entity) {
+            Ok(bound) => bound.tangent(vector),
+            Err(_) => vector,
+        }
+    }
+}
+
+pub struct SurfacePlugin;
+
+impl Plugin for SurfacePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            surface_velocity_system
+                .in_set(BoidStage::ForceApplication)
+                .after(force_application_system)
+                .before(velocity_system),
+        )
+        .add_system(
+            surface_constraint_system
+                .in_set(BoidStage::ForceApplication)
+                .after(velocity_system)
+                .after(boundary_response_system),
+        );
+    }
+}
+
+/// Turns the steered velocity into the tangent plane before the boids move
+pub fn surface_velocity_system(mut query: Query<(&mut Velocity, &SurfaceBound)>) {
+    for (mut vel, bound) in &mut query {
+        vel.vec = bound.tangent(vel.vec);
+    }
+}
+
+/// Puts the boids back on the surface once they moved, and turns their velocity with it
+pub fn surface_constraint_system(
+    mut query: Query<(&mut Transform, &mut Velocity, &mut SurfaceBound)>,
+    heightmap: Option<Res<Heightmap>>,
+    rapier: Res<RapierContext>,
+) {
+    for (mut tf, mut vel, mut bound) in &mut query {
+        let Some((point, normal)) = bound.project(tf.translation, heightmap.as_deref(), &rapier)
+        else {
+            continue;
+        };
+
+        tf.translation = point + normal * bound.altitude;
+        bound.normal = normal;
+        vel.vec = bound.tangent(vel.vec);
+    }
+}
@@ -0,0 +1,170 @@
+use bevy::prelude::*;
+
+/// Ground heights sampled on a regular grid over the XZ plane
+#[derive(Resource, Clone, Debug)]
+pub struct Heightmap {
+    /// World XZ position of the first sample
+    pub origin: Vec2,
+    /// Distance between samples
+    pub spacing: f32,
+    /// Samples along X, the rows follow each other along Z
+    pub width: usize,
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    pub fn from_fn(
+        origin: Vec2,
+        spacing: f32,
+        width: usize,
+        depth: usize,
+        f: impl Fn(Vec2) -> f32,
+    ) -> Self {
+        let heights = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| f(origin + Vec2::new(x as f32, z as f32) * spacing))
+            .collect();
+
+        Self {
+            origin,
+            spacing,
+            width,
+            heights,
+        }
+    }
+
+    /// Level ground covering `size`, centred on `centre`
+    pub fn flat(centre: Vec2, size: Vec2, height: f32) -> Self {
+        let spacing = size.max_element().max(f32::EPSILON);
+        let columns = (size / spacing).ceil().as_uvec2() + UVec2::ONE;
+        Self::from_fn(
+            centre - size * 0.5,
+            spacing,
+            columns.x as usize,
+            columns.y as usize,
+            |_| height,
+        )
+    }
+
+    pub fn depth(&self) -> usize {
+        self.heights.len() / self.width.max(1)
+    }
+
+    fn sample(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    /// Bilinear height under `x`, `z`, None outside the map
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        if self.width < 2 || self.depth() < 2 {
+            return None;
+        }
+
+        let grid = (Vec2::new(x, z) - self.origin) / self.spacing;
+        let last = Vec2::new((self.width - 1) as f32, (self.depth() - 1) as f32);
+        if grid.cmplt(Vec2::ZERO).any() || grid.cmpgt(last).any() {
+            return None;
+        }
+
+        // Stay one cell in from the far edge so the cell always has four corners
+        let cell = grid.floor().min(last - Vec2::ONE);
+        let t = grid - cell;
+        let (cx, cz) = (cell.x as usize, cell.y as usize);
+
+        let near = self.sample(cx, cz) + (self.sample(cx + 1, cz) - self.sample(cx, cz)) * t.x;
+        let far =
+            self.sample(cx, cz + 1) + (self.sample(cx + 1, cz + 1) - self.sample(cx, cz + 1)) * t.x;
+        Some(near + (far - near) * t.y)
+    }
+
+    /// Upward surface normal under `x`, `z`, None outside the map
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vec3> {
+        let h = self.spacing * 0.5;
+        let centre = self.height_at(x, z)?;
+
+        // Central differences, one-sided on the edges of the map
+        let slope = |before: Option<f32>, after: Option<f32>| match (before, after) {
+            (Some(before), Some(after)) => (after - before) / (2.0 * h),
+            (Some(before), None) => (centre - before) / h,
+            (None, Some(after)) => (after - centre) / h,
+            (None, None) => 0.0,
+        };
+        let dx = slope(self.height_at(x - h, z), self.height_at(x + h, z));
+        let dz = slope(self.height_at(x, z - h), self.height_at(x, z + h));
+        Some(Vec3::new(-dx, 1.0, -dz).normalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tilted plane, which bilinear sampling reproduces exactly
+    fn slope() -> Heightmap {
+        Heightmap::from_fn(Vec2::new(-10.0, -10.0), 2.0, 11, 6, |p| p.x * 0.5 + p.y)
+    }
+
+    fn assert_height(map: &Heightmap, x: f32, z: f32, expected: f32) {
+        let height = map.height_at(x, z).unwrap();
+        assert!(
+            (height - expected).abs() < 1e-4,
+            "{} instead of {}",
+            height,
+            expected
+        );
+    }
+
+    #[test]
+    fn heights_at_the_corners() {
+        let map = slope();
+        assert_eq!(map.depth(), 6);
+        assert_height(&map, -10.0, -10.0, -15.0);
+        assert_height(&map, 10.0, -10.0, -5.0);
+        assert_height(&map, -10.0, 0.0, -5.0);
+        assert_height(&map, 10.0, 0.0, 5.0);
+    }
+
+    #[test]
+    fn heights_along_the_edges_and_inside() {
+        let map = slope();
+        assert_height(&map, 10.0, -5.0, 0.0);
+        assert_height(&map, 3.0, 0.0, 1.5);
+        assert_height(&map, 1.3, -7.1, -6.45);
+    }
+
+    #[test]
+    fn nothing_outside_the_map() {
+        let map = slope();
+        assert_eq!(map.height_at(10.1, 0.0), None);
+        assert_eq!(map.height_at(0.0, -10.1), None);
+        assert_eq!(map.normal_at(-11.0, 0.0), None);
+        assert_eq!(
+            Heightmap::from_fn(Vec2::ZERO, 1.0, 1, 4, |_| 0.0).height_at(0.0, 0.0),
+            None
+        );
+    }
+
+    #[test]
+    fn normals_match_the_slope_up_to_the_edges() {
+        let map = slope();
+        let expected = Vec3::new(-0.5, 1.0, -1.0).normalize();
+        for (x, z) in [(0.0, -5.0), (-10.0, -10.0), (10.0, 0.0), (10.0, -5.0)] {
+            let normal = map.normal_at(x, z).unwrap();
+            assert!(
+                normal.abs_diff_eq(expected, 1e-4),
+                "{:?} at {}, {}",
+                normal,
+                x,
+                z
+            );
+        }
+    }
+
+    #[test]
+    fn flat_ground_covers_its_size() {
+        let map = Heightmap::flat(Vec2::new(5.0, 5.0), Vec2::new(20.0, 10.0), 3.0);
+        assert_height(&map, -5.0, 0.0, 3.0);
+        assert_height(&map, 15.0, 10.0, 3.0);
+        assert_eq!(map.normal_at(5.0, 5.0), Some(Vec3::Y));
+    }
+}
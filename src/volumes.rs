@@ -0,0 +1,213 @@
+use crate::behaviours::FactorScale;
+use crate::flock::{BoidsRules, LocalRules};
+use crate::BoidStage;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+#[derive(Clone, Copy, Debug)]
+pub enum VolumeShape {
+    /// Half extents, in the volume's local space
+    Box(Vec3),
+    Sphere(f32),
+    /// Uses the rapier collider on the volume entity, usually a sensor
+    Collider,
+}
+
+/// Parameters changed inside a volume, None leaves the parameter as it is
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParameterOverrides {
+    pub desired_speed: Option<f32>,
+    pub max_force: Option<f32>,
+    pub max_velocity: Option<f32>,
+    /// Multipliers on the behaviour factors
+    pub coherence: Option<f32>,
+    pub separation: Option<f32>,
+    pub alignment: Option<f32>,
+}
+
+impl ParameterOverrides {
+    /// Rules with the overrides applied at `blend`, from 0 (untouched) to 1 (fully applied)
+    pub fn rules(&self, rules: &BoidsRules, blend: f32) -> BoidsRules {
+        let mix = |value: f32, target: Option<f32>| match target {
+            Some(target) => value + (target - value) * blend,
+            None => value,
+        };
+
+        BoidsRules {
+            desired_speed: mix(rules.desired_speed, self.desired_speed),
+            max_force: mix(rules.max_force, self.max_force),
+            max_velocity: mix(rules.max_velocity, self.max_velocity),
+        }
+    }
+
+    pub fn scale(&self, scale: &mut FactorScale, blend: f32) {
+        let mix = |target: Option<f32>| target.map_or(1.0, |t| 1.0 + (t - 1.0) * blend);
+
+        scale.coherence *= mix(self.coherence);
+        scale.separation *= mix(self.separation);
+        scale.alignment *= mix(self.alignment);
+    }
+}
+
+/// Applies its overrides to the boids inside it
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TriggerVolume {
+    pub shape: VolumeShape,
+    pub overrides: ParameterOverrides,
+    /// Seconds taken to blend the overrides in on entry and out on exit
+    pub blend_time: f32,
+    /// When volumes overlap the highest priority wins
+    pub priority: i32,
+}
+
+impl TriggerVolume {
+    pub fn contains(&self, tf: &Transform, point: Vec3) -> bool {
+        let local = tf.rotation.inverse() * (point - tf.translation);
+        match self.shape {
+            VolumeShape::Box(half) => local.abs().cmple(half).all(),
+            VolumeShape::Sphere(radius) => local.length() <= radius,
+            VolumeShape::Collider => false,
+        }
+    }
+}
+
+/// Tracks the volume a boid is in, boids without it ignore volumes
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct VolumeState {
+    /// Volume whose overrides are applied
+    pub active: Option<Entity>,
+    /// How far the overrides are blended in, from 0 to 1
+    pub blend: f32,
+    overrides: ParameterOverrides,
+    /// The boid's own `LocalRules` from before it entered, restored on exit
+    previous: Option<BoidsRules>,
+}
+
+/// Sent when a volume's overrides start applying to a boid, and once they are fully blended out
+pub enum VolumeEvent {
+    Entered { boid: Entity, volume: Entity },
+    Exited { boid: Entity, volume: Entity },
+}
+
+pub struct VolumePlugin;
+
+impl Plugin for VolumePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<VolumeEvent>()
+            .add_system(volume_system.before(BoidStage::ForceCalculation));
+    }
+}
+
+/// A boid reacting to volumes, with what the overrides are written to
+type VolumeBoid = (
+    Entity,
+    &'static Transform,
+    &'static mut VolumeState,
+    Option<&'static mut FactorScale>,
+    Option<&'static mut LocalRules>,
+);
+
+/// Blends the overrides of the volume each boid is in.
+///
+/// When a boid moves from a volume to another, the first one is blended out before the next one
+/// is blended in. The blended rules are written to `LocalRules`, starting from the boid's own
+/// `LocalRules` if it had some, which are put back once the last volume is blended out.
+pub fn volume_system(
+    mut commands: Commands,
+    mut boids: Query<VolumeBoid>,
+    volumes: Query<(Entity, &Transform, &TriggerVolume)>,
+    rapier: Res<RapierContext>,
+    rules: Res<BoidsRules>,
+    time: Res<Time>,
+    mut events: EventWriter<VolumeEvent>,
+) {
+    let delta = time.delta_seconds();
+
+    // Only volumes shaped by their collider need a scene query
+    let is_collider_volume = |e: Entity| match volumes.get(e) {
+        Ok((_, _, vol)) => matches!(vol.shape, VolumeShape::Collider),
+        Err(_) => false,
+    };
+    let any_collider_volume = volumes.iter().any(|(e, _, _)| is_collider_volume(e));
+    let filter = QueryFilter::default().predicate(&is_collider_volume);
+
+    let mut colliders = Vec::new();
+    for (entity, tf, mut state, scale, mut local_rules) in &mut boids {
+        let pos = tf.translation;
+
+        colliders.clear();
+        if any_collider_volume {
+            rapier.intersections_with_point(pos, filter, |e| {
+                colliders.push(e);
+                true
+            });
+        }
+
+        let target = volumes
+            .iter()
+            .filter(|(e, vol_tf, vol)| vol.contains(vol_tf, pos) || colliders.contains(e))
+            .max_by_key(|(_, _, vol)| vol.priority)
+            .map(|(e, _, vol)| (e, vol));
+
+        let blend_time = |volume: Entity| {
+            volumes
+                .get(volume)
+                .map_or(0.0, |(_, _, vol)| vol.blend_time)
+                .max(f32::EPSILON)
+        };
+
+        match (state.active, target) {
+            (Some(active), Some((volume, vol))) if active == volume => {
+                state.overrides = vol.overrides;
+                state.blend = (state.blend + delta / blend_time(active)).min(1.0);
+            }
+            (Some(active), _) => {
+                state.blend = (state.blend - delta / blend_time(active)).max(0.0);
+                if state.blend <= 0.0 {
+                    state.active = None;
+                    match (state.previous.take(), local_rules.as_mut()) {
+                        (Some(previous), Some(local_rules)) => local_rules.0 = previous,
+                        (Some(previous), None) => {
+                            commands.entity(entity).insert(LocalRules(previous));
+                        }
+                        (None, _) => {
+                            commands.entity(entity).remove::<LocalRules>();
+                        }
+                    }
+                    events.send(VolumeEvent::Exited {
+                        boid: entity,
+                        volume: active,
+                    });
+                }
+            }
+            (None, Some((volume, vol))) => {
+                state.active = Some(volume);
+                state.previous = local_rules.as_ref().map(|l| l.0);
+                state.overrides = vol.overrides;
+                state.blend = (delta / blend_time(volume)).min(1.0);
+                events.send(VolumeEvent::Entered {
+                    boid: entity,
+                    volume,
+                });
+            }
+            (None, None) => {}
+        }
+
+        if state.active.is_none() {
+            continue;
+        }
+
+        // Inserted once on entry, updated in place while the boid stays
+        let base = state.previous.unwrap_or(*rules);
+        let local = state.overrides.rules(&base, state.blend);
+        match local_rules {
+            Some(mut local_rules) => local_rules.0 = local,
+            None => {
+                commands.entity(entity).insert(LocalRules(local));
+            }
+        }
+        if let Some(mut scale) = scale {
+            state.overrides.scale(&mut scale, state.blend);
+        }
+    }
+}